@@ -10,7 +10,8 @@ def load_log(fname):
     with open(fname) as f:
         for k in f.readlines():
             if "," in k:
-                stamp, temp = [float(a.strip()) for a in k.strip().split(",")]
+                # Logs of a controlled run have the setpoint and output after these.
+                stamp, temp = [float(a.strip()) for a in k.strip().split(",")][:2]
                 entries.append((stamp / 1000.0, temp))
     return entries
 
@@ -78,6 +79,7 @@ def add_temps(temps):
         new_entries.append(k)
     return new_entries
 
+# Same as TS391AX50 in firmware/src/profile.rs, which the logger follows live.
 solder_reflow_ts391AX50 = [
     (0, 25),
     (30, 100),
@@ -0,0 +1,133 @@
+#![allow(unsafe_code)]
+//! Push button on PA0, connected to ground and using the internal pull up.
+//!
+//! The EXTI interrupt records when the level last changed, the level is only trusted once it has
+//! been stable for the debounce time. A short press and a long press are distinguished.
+use core::cell::RefCell;
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::interrupt::Mutex;
+use stm32f1xx_hal::gpio::{ExtiPin, Input, PA0, PullUp};
+use stm32f1xx_hal::pac::Interrupt;
+use stm32f1xx_hal::pac::interrupt;
+
+type ButtonPin = PA0<Input<PullUp>>;
+
+static GLOBAL_BUTTON: Mutex<RefCell<Option<ButtonPin>>> = Mutex::new(RefCell::new(None));
+static LAST_EDGE: AtomicU32 = AtomicU32::new(0);
+
+/// The level has to be stable for this long before it is trusted.
+pub const DEBOUNCE_MS: u32 = 20;
+/// Presses held at least this long are long presses.
+pub const LONG_PRESS_MS: u32 = 1000;
+
+#[interrupt]
+fn EXTI0() {
+    LAST_EDGE.store(crate::clock::millis(), Ordering::Release);
+    cortex_m::interrupt::free(|cs| {
+        if let Some(b) = GLOBAL_BUTTON.borrow(cs).borrow_mut().deref_mut() {
+            b.clear_interrupt_pending_bit();
+        }
+    });
+}
+
+/// Take the pin, it should already be configured to interrupt on both edges.
+pub fn setup(pin: ButtonPin) {
+    cortex_m::interrupt::free(|cs| {
+        GLOBAL_BUTTON.borrow(cs).borrow_mut().replace(pin);
+    });
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(Interrupt::EXTI0);
+    }
+}
+
+/// Time of the most recent level change.
+pub fn last_edge() -> u32 {
+    LAST_EDGE.load(Ordering::Acquire)
+}
+
+/// The raw level of the button, true if it is pressed.
+pub fn is_pressed() -> bool {
+    cortex_m::interrupt::free(|cs| {
+        GLOBAL_BUTTON
+            .borrow(cs)
+            .borrow()
+            .as_ref()
+            .is_some_and(|b| b.is_low())
+    })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ButtonEvent {
+    /// Released before the long press time.
+    Short,
+    /// Held for the long press time, reported while still held.
+    Long,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Debouncer {
+    pressed: bool,
+    pressed_at: u32,
+    long_reported: bool,
+}
+
+impl Debouncer {
+    /// Update with the raw level at `now`, `last_edge` is the time of the last level change.
+    pub fn update(&mut self, now: u32, last_edge: u32, pressed: bool) -> Option<ButtonEvent> {
+        let stable_for = now.wrapping_sub(last_edge);
+        if stable_for < DEBOUNCE_MS {
+            return None;
+        }
+        match (self.pressed, pressed) {
+            (false, true) => {
+                self.pressed = true;
+                self.pressed_at = last_edge;
+                self.long_reported = false;
+                None
+            }
+            (true, true) => {
+                if !self.long_reported && now.wrapping_sub(self.pressed_at) >= LONG_PRESS_MS {
+                    self.long_reported = true;
+                    Some(ButtonEvent::Long)
+                } else {
+                    None
+                }
+            }
+            (true, false) => {
+                self.pressed = false;
+                (!self.long_reported).then_some(ButtonEvent::Short)
+            }
+            (false, false) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_debouncer() {
+        let mut d = Debouncer::default();
+        assert_eq!(d.update(100, 0, false), None);
+        // Bouncing, ignored until stable.
+        assert_eq!(d.update(105, 104, true), None);
+        assert_eq!(d.update(110, 108, false), None);
+        assert_eq!(d.update(120, 110, true), None);
+        assert_eq!(d.update(130, 110, true), None);
+        assert_eq!(d.update(200, 110, true), None);
+        // Release.
+        assert_eq!(d.update(300, 290, false), None);
+        assert_eq!(d.update(310, 290, false), Some(ButtonEvent::Short));
+        assert_eq!(d.update(320, 290, false), None);
+
+        // Long press is reported once, while held, and not followed by a short press.
+        assert_eq!(d.update(1000, 980, true), None);
+        assert_eq!(d.update(1500, 980, true), None);
+        assert_eq!(d.update(1980, 980, true), Some(ButtonEvent::Long));
+        assert_eq!(d.update(2500, 980, true), None);
+        assert_eq!(d.update(3000, 2900, false), None);
+    }
+}
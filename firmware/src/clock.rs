@@ -18,6 +18,13 @@ pub fn millis() -> u32 {
     GLOBAL_MS.load(core::sync::atomic::Ordering::Acquire)
 }
 
+/// Whether a reading taken at `last` is more than `max_age` milliseconds old at `now`, where
+/// `max_age` is at most `i32::MAX`. A reading from after `now`, taken later in the same pass of the
+/// main loop, counts as fresh.
+pub fn is_stale(now: u32, last: u32, max_age: u32) -> bool {
+    now.wrapping_sub(last) as i32 > max_age as i32
+}
+
 #[interrupt]
 fn TIM2() {
     GLOBAL_MS.fetch_add(1, core::sync::atomic::Ordering::Release);
@@ -109,4 +116,12 @@ mod tests {
         println!("{}", k.elapsed());
         assert!(k.elapsed() == 11);
     }
+
+    #[test]
+    fn test_is_stale() {
+        assert!(!is_stale(1050, 50, 1000));
+        assert!(is_stale(1051, 50, 1000));
+        assert!(is_stale(5, u32::MAX - 1000, 1000));
+        assert!(!is_stale(40, 50, 1000));
+    }
 }
@@ -0,0 +1,299 @@
+//! Commands received over the serial port.
+//!
+//! A command is a single line of ascii, terminated by a newline or carriage return, such that
+//! `echo history > /dev/ttyACM0` works.
+
+use crate::control::Gains;
+use crate::display::{Layout, MAX_DECIMALS, Page, RateEstimator, RateUnit, TemperatureUnit};
+use crate::profile::{PROFILES, Profile};
+use crate::util::StackString;
+
+/// Longest age for `sensor stale`, ages are compared as in `clock::is_stale`.
+const MAX_STALE_AGE: u32 = i32::MAX as u32;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Dump the on-device sample history, in the same format as the live log.
+    History,
+    /// Remove all samples from the on-device history.
+    HistoryClear,
+    /// List the runs in storage.
+    Runs,
+    /// Erase all runs in storage, this starts a new run.
+    RunsErase,
+    /// Download the samples of a run in storage, in the same format as the live log.
+    Download(u32),
+    /// Switch the display to another page.
+    Page(Page),
+    /// Switch the display to the next page, like a press of the button.
+    PageNext,
+    /// Set the layout of the main page.
+    Layout(Layout),
+    /// Set the number of decimals of the temperature on the main page.
+    Decimals(u8),
+    /// Start a reflow run following a profile.
+    ReflowStart(&'static Profile),
+    /// Stop the reflow run.
+    ReflowStop,
+    /// Drive the oven from the reflow runs.
+    ControlOn,
+    /// Stop driving the oven.
+    ControlOff,
+    /// Set the gains of the oven controller.
+    ControlGains(Gains),
+    /// Reset the latched interlock.
+    InterlockReset,
+    /// Set the limit of the interlock, in the temperature unit.
+    InterlockLimit(f32),
+    /// Set the age in milliseconds after which a reading is stale.
+    SensorStale(u32),
+    /// Report the statistics since the last reset.
+    Stats,
+    /// Reset the statistics.
+    StatsReset,
+    /// Set the threshold for the time above statistic, in the temperature unit.
+    StatsThreshold(f32),
+    /// Set the short rate of change window, in milliseconds.
+    RateShort(u32),
+    /// Set the long rate of change window, in milliseconds.
+    RateLong(u32),
+    /// Set how the rates of change are determined.
+    RateEstimator(RateEstimator),
+    /// Set the unit the rates of change are shown in.
+    RateUnit(RateUnit),
+    /// Set the unit temperatures are shown and logged in.
+    Unit(TemperatureUnit),
+    /// Set the contrast of the display.
+    ScreenContrast(u8),
+    /// Set the inactivity in seconds before the display dims, zero to never dim.
+    ScreenDim(u32),
+    /// Set the inactivity in seconds before the display switches off, zero to never switch off.
+    ScreenOff(u32),
+    /// Set the period in seconds of the pixel shift, zero to not shift.
+    ScreenShift(u32),
+    /// Wake the display, like a press of the button.
+    ScreenWake,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let command = match (words.next()?, words.next()) {
+            ("history", None) => Command::History,
+            ("history", Some("clear")) => Command::HistoryClear,
+            ("runs", None) => Command::Runs,
+            ("runs", Some("erase")) => Command::RunsErase,
+            ("download", Some(run)) => Command::Download(run.parse().ok()?),
+            ("page", Some("main")) => Command::Page(Page::Main),
+            ("page", Some("graph")) => Command::Page(Page::Graph),
+            ("page", Some("stats")) => Command::Page(Page::Statistics),
+            ("page", Some("sensor")) => Command::Page(Page::Sensor),
+            ("page", Some("reflow")) => Command::Page(Page::Reflow),
+            ("page", Some("next")) => Command::PageNext,
+            ("layout", Some("normal")) => Command::Layout(Layout::Normal),
+            ("layout", Some("big")) => Command::Layout(Layout::Big),
+            ("decimals", Some(d)) => {
+                Command::Decimals(d.parse().ok().filter(|d| *d <= MAX_DECIMALS)?)
+            }
+            ("reflow", Some("start")) => Command::ReflowStart(match words.next() {
+                Some(name) => Profile::find(name)?,
+                None => PROFILES[0],
+            }),
+            ("reflow", Some("stop")) => Command::ReflowStop,
+            ("control", Some("on")) => Command::ControlOn,
+            ("control", Some("off")) => Command::ControlOff,
+            ("control", Some("pid")) => Command::ControlGains(Gains {
+                kp: words.next()?.parse().ok()?,
+                ki: words.next()?.parse().ok()?,
+                kd: words.next()?.parse().ok()?,
+            }),
+            ("interlock", Some("reset")) => Command::InterlockReset,
+            ("interlock", Some("limit")) => Command::InterlockLimit(words.next()?.parse().ok()?),
+            ("sensor", Some("stale")) => {
+                Command::SensorStale(words.next()?.parse().ok().filter(|a| *a <= MAX_STALE_AGE)?)
+            }
+            ("stats", None) => Command::Stats,
+            ("stats", Some("reset")) => Command::StatsReset,
+            ("stats", Some("threshold")) => Command::StatsThreshold(words.next()?.parse().ok()?),
+            ("rate", Some("short")) => Command::RateShort(words.next()?.parse().ok()?),
+            ("rate", Some("long")) => Command::RateLong(words.next()?.parse().ok()?),
+            ("rate", Some("fit")) => Command::RateEstimator(RateEstimator::LeastSquares),
+            ("rate", Some("difference")) => Command::RateEstimator(RateEstimator::Difference),
+            ("rate", Some("unit")) => Command::RateUnit(match words.next()? {
+                "s" => RateUnit::PerSecond,
+                "min" => RateUnit::PerMinute,
+                "h" => RateUnit::PerHour,
+                "k/min" => RateUnit::KelvinPerMinute,
+                _ => return None,
+            }),
+            ("unit", Some("c")) => Command::Unit(TemperatureUnit::Celsius),
+            ("unit", Some("f")) => Command::Unit(TemperatureUnit::Fahrenheit),
+            ("unit", Some("k")) => Command::Unit(TemperatureUnit::Kelvin),
+            ("screen", Some("contrast")) => Command::ScreenContrast(words.next()?.parse().ok()?),
+            ("screen", Some("dim")) => Command::ScreenDim(words.next()?.parse().ok()?),
+            ("screen", Some("off")) => Command::ScreenOff(words.next()?.parse().ok()?),
+            ("screen", Some("shift")) => Command::ScreenShift(words.next()?.parse().ok()?),
+            ("screen", Some("wake")) => Command::ScreenWake,
+            _ => return None,
+        };
+        if words.next().is_some() {
+            return None;
+        }
+        Some(command)
+    }
+}
+
+/// Collects received bytes into lines.
+#[derive(Default)]
+pub struct LineBuffer {
+    line: StackString,
+    /// Set if the current line didn't fit, it is discarded at the line ending.
+    overflow: bool,
+}
+
+impl LineBuffer {
+    /// Add a received byte, returns the line when a line ending completes it.
+    pub fn push(&mut self, byte: u8) -> Option<&str> {
+        if byte == b'\n' || byte == b'\r' {
+            let complete = !self.overflow && self.line.size != 0;
+            let size = self.line.size;
+            self.line.size = 0;
+            self.overflow = false;
+            if complete {
+                return core::str::from_utf8(&self.line.buffer[0..size]).ok();
+            }
+            return None;
+        }
+        if self.line.len() == StackString::capacity() {
+            self.overflow = true;
+        } else {
+            self.line.buffer[self.line.size] = byte;
+            self.line.size += 1;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_command_parse() {
+        assert_eq!(Command::parse("history"), Some(Command::History));
+        assert_eq!(Command::parse("  history  "), Some(Command::History));
+        assert_eq!(Command::parse("history clear"), Some(Command::HistoryClear));
+        assert_eq!(Command::parse("history foo"), None);
+        assert_eq!(Command::parse("history clear foo"), None);
+        assert_eq!(Command::parse("runs"), Some(Command::Runs));
+        assert_eq!(Command::parse("runs erase"), Some(Command::RunsErase));
+        assert_eq!(Command::parse("download 3"), Some(Command::Download(3)));
+        assert_eq!(Command::parse("download"), None);
+        assert_eq!(Command::parse("download -1"), None);
+        assert_eq!(
+            Command::parse("page graph"),
+            Some(Command::Page(Page::Graph))
+        );
+        assert_eq!(Command::parse("page next"), Some(Command::PageNext));
+        assert_eq!(Command::parse("page foo"), None);
+        assert_eq!(
+            Command::parse("layout big"),
+            Some(Command::Layout(Layout::Big))
+        );
+        assert_eq!(Command::parse("layout huge"), None);
+        assert_eq!(Command::parse("decimals 1"), Some(Command::Decimals(1)));
+        assert_eq!(Command::parse("decimals 5"), None);
+        assert_eq!(
+            Command::parse("reflow start"),
+            Some(Command::ReflowStart(&crate::profile::TS391AX50))
+        );
+        assert_eq!(
+            Command::parse("reflow start ts391ax50"),
+            Some(Command::ReflowStart(&crate::profile::TS391AX50))
+        );
+        assert_eq!(Command::parse("reflow start foo"), None);
+        assert_eq!(Command::parse("reflow stop"), Some(Command::ReflowStop));
+        assert_eq!(Command::parse("control on"), Some(Command::ControlOn));
+        assert_eq!(Command::parse("control off"), Some(Command::ControlOff));
+        assert_eq!(
+            Command::parse("control pid 0.1 0.002 0.5"),
+            Some(Command::ControlGains(Gains {
+                kp: 0.1,
+                ki: 0.002,
+                kd: 0.5
+            }))
+        );
+        assert_eq!(Command::parse("control pid 0.1 0.002"), None);
+        assert_eq!(
+            Command::parse("interlock reset"),
+            Some(Command::InterlockReset)
+        );
+        assert_eq!(
+            Command::parse("interlock limit 250"),
+            Some(Command::InterlockLimit(250.0))
+        );
+        assert_eq!(Command::parse("interlock limit"), None);
+        assert_eq!(
+            Command::parse("sensor stale 2000"),
+            Some(Command::SensorStale(2000))
+        );
+        assert_eq!(Command::parse("sensor stale"), None);
+        assert_eq!(Command::parse("sensor stale 2147483648"), None);
+        assert_eq!(Command::parse("stats"), Some(Command::Stats));
+        assert_eq!(Command::parse("stats reset"), Some(Command::StatsReset));
+        assert_eq!(
+            Command::parse("stats threshold 183.5"),
+            Some(Command::StatsThreshold(183.5))
+        );
+        assert_eq!(Command::parse("stats threshold"), None);
+        assert_eq!(Command::parse("stats threshold 1 2"), None);
+        assert_eq!(
+            Command::parse("rate long 30000"),
+            Some(Command::RateLong(30000))
+        );
+        assert_eq!(
+            Command::parse("rate unit h"),
+            Some(Command::RateUnit(RateUnit::PerHour))
+        );
+        assert_eq!(Command::parse("rate unit day"), None);
+        assert_eq!(
+            Command::parse("unit f"),
+            Some(Command::Unit(TemperatureUnit::Fahrenheit))
+        );
+        assert_eq!(Command::parse("unit r"), None);
+        assert_eq!(
+            Command::parse("screen contrast 255"),
+            Some(Command::ScreenContrast(255))
+        );
+        assert_eq!(Command::parse("screen contrast 256"), None);
+        assert_eq!(Command::parse("screen off 0"), Some(Command::ScreenOff(0)));
+        assert_eq!(Command::parse("screen wake"), Some(Command::ScreenWake));
+        assert_eq!(
+            Command::parse("rate difference"),
+            Some(Command::RateEstimator(RateEstimator::Difference))
+        );
+        assert_eq!(Command::parse(""), None);
+        assert_eq!(Command::parse("foo"), None);
+    }
+
+    #[test]
+    fn test_line_buffer() {
+        let mut lines = LineBuffer::default();
+        let mut received = vec![];
+        for b in b"history\r\n\nhistory clear\n".iter() {
+            if let Some(line) = lines.push(*b) {
+                received.push(line.to_owned());
+            }
+        }
+        assert_eq!(received, vec!["history", "history clear"]);
+
+        // Overly long lines are discarded as a whole.
+        for _ in 0..100 {
+            assert_eq!(lines.push(b'a'), None);
+        }
+        assert_eq!(lines.push(b'\n'), None);
+        for b in b"history".iter() {
+            assert_eq!(lines.push(*b), None);
+        }
+        assert_eq!(lines.push(b'\n'), Some("history"));
+    }
+}
@@ -0,0 +1,406 @@
+//! Closed loop control of a reflow oven through a solid state relay.
+//!
+//! A PID controller follows the target temperature of the profile, its output is the fraction of
+//! time the relay is on. The relay switches in windows of a few seconds, zero crossing SSRs only
+//! switch at the zero crossings of the mains anyway.
+//!
+//! The output is cut off when the sensor faults, when no reading arrived for a while or when the
+//! temperature exceeds a limit. A cutoff holds until the control is started again.
+
+use crate::clock;
+
+/// Length of a time proportioning window in milliseconds.
+pub const WINDOW_MS: u32 = 2000;
+/// Age of the last reading after which the output is cut off, see `clock::is_stale`.
+pub const MAX_AGE_MS: u32 = 1000;
+/// Temperature above which the output is cut off, well above the peak of the profiles.
+pub const MAX_TEMPERATURE: f32 = 260.0;
+/// Time constant of the filter on the derivative in seconds, the resolution of the sensor makes the
+/// rate of change between two samples noisy.
+const DERIVATIVE_FILTER: f32 = 2.0;
+
+/// Gains of the controller, the output ranges from 0 to 1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Gains {
+    /// Per degree of error.
+    pub kp: f32,
+    /// Per degree second of error.
+    pub ki: f32,
+    /// Per degree per second of rate of change.
+    pub kd: f32,
+}
+
+/// Tuned against the simulated toaster oven in the tests.
+pub const DEFAULT_GAINS: Gains = Gains {
+    kp: 0.08,
+    ki: 0.004,
+    kd: 0.3,
+};
+
+/// PID controller, with the derivative on the measurement such that the steps of the setpoint
+/// don't kick the output.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Pid {
+    /// Integral term, already multiplied by the integral gain.
+    integral: f32,
+    /// Filtered rate of change of the measurement.
+    derivative: f32,
+    last: Option<f32>,
+}
+
+impl Pid {
+    /// The output for a measurement `dt` seconds after the previous one.
+    pub fn update(&mut self, gains: &Gains, setpoint: f32, measurement: f32, dt: f32) -> f32 {
+        let error = setpoint - measurement;
+        if let Some(last) = self.last
+            && dt > 0.0
+        {
+            let rate = (measurement - last) / dt;
+            self.derivative += (rate - self.derivative) * dt / (DERIVATIVE_FILTER + dt);
+        }
+        self.last = Some(measurement);
+
+        let integral = self.integral + gains.ki * error * dt;
+        let output = gains.kp * error + integral - gains.kd * self.derivative;
+        // Only integrate while that doesn't push the output further into saturation.
+        if (0.0..=1.0).contains(&output) || (output > 1.0) != (error > 0.0) {
+            self.integral = integral.clamp(0.0, 1.0);
+        }
+        output.clamp(0.0, 1.0)
+    }
+}
+
+/// Switches an output on for a fraction of each window.
+#[derive(Copy, Clone, Debug)]
+pub struct TimeProportional {
+    period: u32,
+    /// Start of the current window.
+    start: u32,
+    /// Fraction of the current window the output is on.
+    on: f32,
+    /// Fraction for the next window.
+    duty: f32,
+}
+
+impl TimeProportional {
+    pub fn new(period: u32, now: u32) -> Self {
+        Self {
+            period,
+            start: now,
+            on: 0.0,
+            duty: 0.0,
+        }
+    }
+
+    /// Set the fraction of the time the output is on, from the next window.
+    pub fn set(&mut self, duty: f32) {
+        self.duty = duty.clamp(0.0, 1.0);
+    }
+
+    /// Whether the output is on at `now`.
+    pub fn output(&mut self, now: u32) -> bool {
+        if now.wrapping_sub(self.start) >= self.period {
+            self.start = now;
+            self.on = self.duty;
+        }
+        (now.wrapping_sub(self.start) as f32) < self.on * self.period as f32
+    }
+}
+
+/// Reasons the output was cut off.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Cutoff {
+    /// Reading the sensor failed.
+    SensorFault,
+    /// No reading arrived in time.
+    Stale,
+    /// The temperature exceeded the limit.
+    OverTemperature,
+}
+
+impl Cutoff {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cutoff::SensorFault => "sensor fault",
+            Cutoff::Stale => "stale reading",
+            Cutoff::OverTemperature => "over temperature",
+        }
+    }
+}
+
+/// The controller and relay output, together with the safety cutoffs.
+#[derive(Copy, Clone, Debug)]
+pub struct Control {
+    pub gains: Gains,
+    pub max_temperature: f32,
+    active: bool,
+    pid: Pid,
+    relay: TimeProportional,
+    /// Time of the last reading.
+    last: u32,
+    setpoint: Option<f32>,
+    duty: f32,
+    cutoff: Option<Cutoff>,
+}
+
+impl Control {
+    pub fn new(gains: Gains) -> Self {
+        Self {
+            gains,
+            max_temperature: MAX_TEMPERATURE,
+            active: false,
+            pid: Pid::default(),
+            relay: TimeProportional::new(WINDOW_MS, 0),
+            last: 0,
+            setpoint: None,
+            duty: 0.0,
+            cutoff: None,
+        }
+    }
+
+    /// Start controlling at `now`, this clears a cutoff.
+    pub fn start(&mut self, now: u32) {
+        *self = Self {
+            active: true,
+            relay: TimeProportional::new(WINDOW_MS, now),
+            last: now,
+            max_temperature: self.max_temperature,
+            ..Self::new(self.gains)
+        };
+    }
+
+    /// Stop controlling, the output stays off.
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.setpoint = None;
+        self.duty = 0.0;
+        self.relay.set(0.0);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn cutoff(&self) -> Option<Cutoff> {
+        self.cutoff
+    }
+
+    /// Target temperature of the last update, none without a target.
+    pub fn setpoint(&self) -> Option<f32> {
+        self.setpoint
+    }
+
+    /// Fraction of the time the relay is on, from 0 to 1.
+    pub fn duty(&self) -> f32 {
+        self.duty
+    }
+
+    fn trip(&mut self, cutoff: Cutoff) -> Option<Cutoff> {
+        if !self.active || self.cutoff.is_some() {
+            return None;
+        }
+        self.cutoff = Some(cutoff);
+        self.duty = 0.0;
+        self.relay.set(0.0);
+        Some(cutoff)
+    }
+
+    /// Update with a reading at `now`, none if reading the sensor failed, towards the target
+    /// temperature `setpoint`. Returns the cutoff if this trips one.
+    pub fn update(
+        &mut self,
+        now: u32,
+        reading: Option<f32>,
+        setpoint: Option<f32>,
+    ) -> Option<Cutoff> {
+        let Some(temperature) = reading else {
+            return self.trip(Cutoff::SensorFault);
+        };
+        if temperature > self.max_temperature {
+            return self.trip(Cutoff::OverTemperature);
+        }
+        if !self.active || self.cutoff.is_some() {
+            return None;
+        }
+        let dt = now.wrapping_sub(self.last) as f32 / 1000.0;
+        self.last = now;
+        self.setpoint = setpoint;
+        self.duty = match setpoint {
+            Some(setpoint) => self.pid.update(&self.gains, setpoint, temperature, dt),
+            None => 0.0,
+        };
+        self.relay.set(self.duty);
+        None
+    }
+
+    /// Check the age of the last reading at `now`, returns the cutoff if this trips one.
+    pub fn service(&mut self, now: u32) -> Option<Cutoff> {
+        if clock::is_stale(now, self.last, MAX_AGE_MS) {
+            return self.trip(Cutoff::Stale);
+        }
+        None
+    }
+
+    /// Whether the relay is on at `now`.
+    pub fn relay(&mut self, now: u32) -> bool {
+        let on = self.relay.output(now);
+        on && self.active && self.cutoff.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{Run, TS391AX50};
+
+    /// A toaster oven; the heating elements warm the air and the board, which lose heat to the
+    /// room. The thermocouple is on the board.
+    struct Oven {
+        element: f32,
+        board: f32,
+    }
+
+    impl Oven {
+        const AMBIENT: f32 = 25.0;
+        /// Heater power in Watt.
+        const POWER: f32 = 1500.0;
+        /// Heat capacities in J/K.
+        const ELEMENT_CAPACITY: f32 = 100.0;
+        const BOARD_CAPACITY: f32 = 300.0;
+        /// Conductances in W/K.
+        const ELEMENT_TO_BOARD: f32 = 12.0;
+        const BOARD_TO_AMBIENT: f32 = 4.0;
+
+        fn new() -> Self {
+            Self {
+                element: Self::AMBIENT,
+                board: Self::AMBIENT,
+            }
+        }
+
+        fn step(&mut self, on: bool, dt: f32) {
+            let heat = if on { Self::POWER } else { 0.0 };
+            let transfer = (self.element - self.board) * Self::ELEMENT_TO_BOARD;
+            let loss = (self.board - Self::AMBIENT) * Self::BOARD_TO_AMBIENT;
+            self.element += (heat - transfer) * dt / Self::ELEMENT_CAPACITY;
+            self.board += (transfer - loss) * dt / Self::BOARD_CAPACITY;
+        }
+
+        /// The reading of the sensor, at its resolution.
+        fn reading(&self) -> f32 {
+            (self.board / crate::mcp9600::TEMPERATURE_RESOLUTION).round()
+                * crate::mcp9600::TEMPERATURE_RESOLUTION
+        }
+    }
+
+    #[test]
+    fn test_pid() {
+        let mut pid = Pid::default();
+        let gains = Gains {
+            kp: 0.1,
+            ki: 0.01,
+            kd: 0.0,
+        };
+        // Saturated, the integral doesn't wind up.
+        for _ in 0..100 {
+            assert_eq!(pid.update(&gains, 100.0, 0.0, 1.0), 1.0);
+        }
+        assert_eq!(pid.integral, 0.0);
+        // Proportional and integral.
+        assert!((pid.update(&gains, 100.0, 95.0, 1.0) - 0.55).abs() < 1e-6);
+        assert!((pid.update(&gains, 100.0, 100.0, 1.0) - 0.05).abs() < 1e-6);
+        // Never below zero.
+        assert_eq!(pid.update(&gains, 100.0, 150.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_time_proportional() {
+        let mut relay = TimeProportional::new(1000, 0);
+        relay.set(0.25);
+        // The duty takes effect from the next window.
+        assert!(!relay.output(0));
+        let on = (1000..3000).filter(|t| relay.output(*t)).count();
+        assert_eq!(on, 500);
+        relay.set(2.0);
+        assert!((3000..4000).all(|t| relay.output(t)));
+        relay.set(0.0);
+        assert!(!(4000..5000).any(|t| relay.output(t)));
+    }
+
+    #[test]
+    fn test_cutoff() {
+        let mut control = Control::new(DEFAULT_GAINS);
+        // Nothing happens while not active.
+        assert_eq!(control.update(0, None, Some(100.0)), None);
+        assert_eq!(control.service(5000), None);
+        assert!(!control.relay(5000));
+
+        control.start(10_000);
+        assert_eq!(control.update(10_050, Some(25.0), Some(100.0)), None);
+        assert_eq!(control.duty(), 1.0);
+        assert!(control.relay(12_000));
+        // A sensor fault cuts the output off, and it stays off.
+        assert_eq!(
+            control.update(12_050, None, Some(100.0)),
+            Some(Cutoff::SensorFault)
+        );
+        assert!(!control.relay(12_100));
+        assert_eq!(control.update(12_100, Some(25.0), Some(100.0)), None);
+        assert!(!control.relay(14_100));
+        assert_eq!(control.cutoff(), Some(Cutoff::SensorFault));
+
+        // Starting again clears it.
+        control.start(20_000);
+        assert_eq!(control.cutoff(), None);
+        assert_eq!(control.update(20_050, Some(25.0), Some(100.0)), None);
+        assert_eq!(control.service(20_000), None);
+        assert_eq!(control.service(21_000), None);
+        assert_eq!(control.service(21_051), Some(Cutoff::Stale));
+        assert!(!control.relay(22_100));
+
+        control.start(30_000);
+        assert_eq!(
+            control.update(30_050, Some(MAX_TEMPERATURE + 1.0), Some(100.0)),
+            Some(Cutoff::OverTemperature)
+        );
+
+        // Without a target the output is off.
+        control.start(40_000);
+        assert_eq!(control.update(40_050, Some(25.0), None), None);
+        assert_eq!(control.duty(), 0.0);
+        assert!(!control.relay(42_100));
+    }
+
+    #[test]
+    fn test_follow_profile() {
+        let mut oven = Oven::new();
+        let mut control = Control::new(DEFAULT_GAINS);
+        let run = Run::new(&TS391AX50, 0);
+        control.start(0);
+
+        let mut peak: f32 = 0.0;
+        let mut worst: f32 = 0.0;
+        // Simulate in steps of 10 ms, with a reading every 50 ms like the sensor task.
+        for now in (0..TS391AX50.duration_ms()).step_by(10) {
+            if now % 50 == 0 {
+                assert_eq!(
+                    control.update(now, Some(oven.reading()), run.target(now)),
+                    None
+                );
+            }
+            assert_eq!(control.service(now), None);
+            oven.step(control.relay(now), 0.01);
+
+            // The oven doesn't keep up with the start of the preheat ramp, nor cools as fast as the
+            // profile asks for, so it is checked from half a minute after the preheat ramp until
+            // the peak.
+            peak = peak.max(oven.board);
+            if (60_000..=210_000).contains(&now) {
+                let error = oven.board - run.target(now).unwrap();
+                worst = worst.max(error.abs());
+            }
+        }
+        assert!(worst < 5.0, "worst error {}", worst);
+        assert!((230.0..240.0).contains(&peak), "peak {}", peak);
+    }
+}
@@ -0,0 +1,232 @@
+//! The OLED controllers the display can be driven with.
+//!
+//! Both the SSD1306 and the SH1106 store the pixels in pages of 8 rows, with one byte per column.
+//! The delta buffer only needs to write runs of bytes into a page, which is all this abstracts.
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+use ssd1306::Ssd1306;
+use ssd1306::mode::{BasicMode, DisplayConfig};
+use ssd1306::prelude::{Brightness, DisplayRotation};
+#[cfg(feature = "oled-64x48")]
+use ssd1306::size::DisplaySize64x48;
+#[cfg(not(any(feature = "oled-128x64", feature = "oled-64x48", feature = "sh1106")))]
+use ssd1306::size::DisplaySize128x32;
+use ssd1306::size::{DisplaySize, DisplaySize128x64};
+
+use crate::display_buffer::DeltaSize;
+
+pub trait Controller {
+    type Size: DisplaySize + DeltaSize;
+
+    /// Initialise the controller, leaving the display on with its memory cleared.
+    fn init(&mut self) -> Result<(), DisplayError>;
+
+    /// Write `data` into `page`, starting at `column`; each byte is a column of 8 pixels.
+    fn draw_page(&mut self, page: u8, column: u8, data: &[u8]) -> Result<(), DisplayError>;
+
+    fn set_display_on(&mut self, on: bool) -> Result<(), DisplayError>;
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<(), DisplayError>;
+}
+
+impl<DI, SIZE> Controller for Ssd1306<DI, SIZE, BasicMode>
+where
+    DI: WriteOnlyDataCommand,
+    SIZE: DisplaySize + DeltaSize,
+{
+    type Size = SIZE;
+
+    fn init(&mut self) -> Result<(), DisplayError> {
+        DisplayConfig::init(self)
+    }
+
+    fn draw_page(&mut self, page: u8, column: u8, data: &[u8]) -> Result<(), DisplayError> {
+        // Narrow panels sit at an offset in the controller's columns, the panel isn't rotated.
+        let column = column + SIZE::OFFSETX;
+        let y = page * 8;
+        self.set_draw_area((column, y), (column + data.len() as u8, y | 7))?;
+        self.draw(data)
+    }
+
+    fn set_display_on(&mut self, on: bool) -> Result<(), DisplayError> {
+        Ssd1306::set_display_on(self, on)
+    }
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<(), DisplayError> {
+        self.set_brightness(Brightness::custom(2, contrast))
+    }
+}
+
+/// The SH1106 has 132 columns of memory, the 128 visible columns are centered in it.
+const SH1106_COLUMN_OFFSET: u8 = 2;
+const SH1106_COLUMNS: usize = 132;
+const SH1106_PAGES: u8 = 8;
+
+/// Driver for 128x64 panels with a SH1106 controller, which only supports page addressing.
+pub struct Sh1106<DI> {
+    interface: DI,
+}
+
+impl<DI: WriteOnlyDataCommand> Sh1106<DI> {
+    pub fn new(interface: DI) -> Self {
+        Self { interface }
+    }
+
+    fn command(&mut self, command: &[u8]) -> Result<(), DisplayError> {
+        self.interface.send_commands(DataFormat::U8(command))
+    }
+
+    /// Set the page and the column in memory the next data is written to.
+    fn set_position(&mut self, page: u8, column: u8) -> Result<(), DisplayError> {
+        self.command(&[0xb0 | page, column & 0x0f, 0x10 | (column >> 4)])
+    }
+
+    pub fn release(self) -> DI {
+        self.interface
+    }
+}
+
+impl<DI: WriteOnlyDataCommand> Controller for Sh1106<DI> {
+    type Size = DisplaySize128x64;
+
+    fn init(&mut self) -> Result<(), DisplayError> {
+        self.command(&[
+            0xae, // Display off.
+            0xd5, 0x80, // Clock divider and oscillator frequency.
+            0xa8, 0x3f, // Multiplex ratio, 64 rows.
+            0xd3, 0x00, // Display offset.
+            0x40, // Start line.
+            0xad, 0x8b, // DC-DC converter on.
+            0xa1, // Segment remap, column 131 is SEG0.
+            0xc8, // Scan from COM63 to COM0.
+            0xda, 0x12, // Alternative COM pin configuration.
+            0x81, 0x80, // Contrast.
+            0xd9, 0x22, // Precharge periods.
+            0xdb, 0x35, // VCOM deselect level.
+            0xa4, // Show the memory contents.
+            0xa6, // Not inverted.
+        ])?;
+        // Clear all memory, including the columns that aren't visible.
+        for page in 0..SH1106_PAGES {
+            self.set_position(page, 0)?;
+            self.interface
+                .send_data(DataFormat::U8(&[0u8; SH1106_COLUMNS]))?;
+        }
+        self.set_display_on(true)
+    }
+
+    fn draw_page(&mut self, page: u8, column: u8, data: &[u8]) -> Result<(), DisplayError> {
+        self.set_position(page, column + SH1106_COLUMN_OFFSET)?;
+        self.interface.send_data(DataFormat::U8(data))
+    }
+
+    fn set_display_on(&mut self, on: bool) -> Result<(), DisplayError> {
+        self.command(&[if on { 0xaf } else { 0xae }])
+    }
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<(), DisplayError> {
+        self.command(&[0x81, contrast])
+    }
+}
+
+// The panel the firmware is built for, selected with features; a 128x32 SSD1306 by default.
+#[cfg(all(feature = "oled-128x64", feature = "oled-64x48"))]
+compile_error!("features `oled-128x64` and `oled-64x48` select different panels");
+#[cfg(all(feature = "sh1106", feature = "oled-64x48"))]
+compile_error!("feature `sh1106` is for 128x64 panels, it can't be used with `oled-64x48`");
+#[cfg(all(feature = "sh1106", feature = "oled-128x64"))]
+compile_error!("feature `sh1106` selects a 128x64 panel, `oled-128x64` is for the SSD1306");
+#[cfg(all(feature = "oled-128x64", not(feature = "sh1106")))]
+type PanelSize = DisplaySize128x64;
+#[cfg(all(feature = "oled-64x48", not(feature = "sh1106")))]
+type PanelSize = DisplaySize64x48;
+#[cfg(not(any(feature = "oled-128x64", feature = "oled-64x48", feature = "sh1106")))]
+type PanelSize = DisplaySize128x32;
+
+#[cfg(not(feature = "sh1106"))]
+pub type Panel<DI> = Ssd1306<DI, PanelSize, BasicMode>;
+#[cfg(feature = "sh1106")]
+pub type Panel<DI> = Sh1106<DI>;
+
+/// Create the controller for the panel the firmware is built for.
+pub fn panel<DI: WriteOnlyDataCommand>(interface: DI) -> Panel<DI> {
+    #[cfg(not(feature = "sh1106"))]
+    {
+        Ssd1306::new(interface, PanelSize {}, DisplayRotation::Rotate0)
+    }
+    #[cfg(feature = "sh1106")]
+    {
+        Sh1106::new(interface)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use ssd1306::size::{DisplaySize64x48, DisplaySize128x32};
+
+    /// Records everything sent to the display, such that it can be compared on the host.
+    #[derive(Default, Debug)]
+    pub struct RecordingInterface {
+        pub commands: Vec<u8>,
+        /// Data writes, with the number of command bytes sent before each.
+        pub data: Vec<(usize, Vec<u8>)>,
+    }
+
+    impl WriteOnlyDataCommand for RecordingInterface {
+        fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result<(), DisplayError> {
+            match cmd {
+                DataFormat::U8(v) => self.commands.extend_from_slice(v),
+                _ => return Err(DisplayError::DataFormatNotImplemented),
+            }
+            Ok(())
+        }
+
+        fn send_data(&mut self, buf: DataFormat<'_>) -> Result<(), DisplayError> {
+            match buf {
+                DataFormat::U8(v) => self.data.push((self.commands.len(), v.to_vec())),
+                _ => return Err(DisplayError::DataFormatNotImplemented),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sh1106() {
+        let mut d = Sh1106::new(RecordingInterface::default());
+        d.init().unwrap();
+        let i = d.release();
+        assert_eq!(i.data.len(), 8);
+        assert!(i.data.iter().all(|(_, v)| v.len() == 132));
+        assert_eq!(i.commands.last(), Some(&0xaf));
+
+        let mut d = Sh1106::new(RecordingInterface::default());
+        // Columns are offset by two, the column address is split in nibbles.
+        d.draw_page(3, 20, &[1, 2, 3]).unwrap();
+        d.set_contrast(0x10).unwrap();
+        d.set_display_on(false).unwrap();
+        let i = d.release();
+        assert_eq!(i.commands, [0xb3, 0x06, 0x11, 0x81, 0x10, 0xae]);
+        assert_eq!(i.data, [(3, vec![1, 2, 3])]);
+    }
+
+    #[test]
+    fn test_ssd1306() {
+        // Without an address mode set, only the column window is sent.
+        let mut d = Ssd1306::new(
+            RecordingInterface::default(),
+            DisplaySize128x32,
+            DisplayRotation::Rotate0,
+        );
+        d.draw_page(3, 20, &[1, 2, 3]).unwrap();
+        assert_eq!(d.release().commands, [0x21, 20, 22]);
+
+        // The 64 columns of this panel are the middle ones of the controller.
+        let mut d = Ssd1306::new(
+            RecordingInterface::default(),
+            DisplaySize64x48,
+            DisplayRotation::Rotate0,
+        );
+        d.draw_page(3, 20, &[1, 2, 3]).unwrap();
+        assert_eq!(d.release().commands, [0x21, 52, 54]);
+    }
+}
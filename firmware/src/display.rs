@@ -1,40 +1,154 @@
 use core::u32;
 
-use embedded_graphics::mono_font::MonoTextStyle;
 use embedded_graphics::mono_font::ascii::FONT_9X15;
 use embedded_graphics::mono_font::iso_8859_9::{FONT_5X7, FONT_8X13_BOLD};
 use embedded_graphics::mono_font::iso_8859_16::FONT_8X13;
 
 use ssd1306::mode::{BasicMode, BufferedGraphicsMode};
-use ssd1306::size::DisplaySize128x32;
 use ssd1306::{I2CDisplayInterface, Ssd1306, prelude::*};
 
 use embedded_graphics::{
-    mono_font::{MonoTextStyleBuilder, ascii::FONT_6X10, ascii::FONT_9X15_BOLD, ascii::FONT_10X20},
+    mono_font::{ascii::FONT_6X10, ascii::FONT_9X15_BOLD, ascii::FONT_10X20},
     pixelcolor::BinaryColor,
     prelude::*,
-    text::{Baseline, Text},
+    primitives::Rectangle,
 };
 
+use crate::graph::Sparkline;
+use crate::profile::Run;
+use crate::screensaver::{self, Power, ScreenSaver};
+use crate::seven_segment::FONT_SEVEN_SEGMENT_16X32;
+use crate::statistics::Statistics;
+
+/// Number of columns of the graph view, at five seconds per column this spans eight minutes.
+pub const GRAPH_COLUMNS: usize = 96;
+
+/// The pages the display can show, the button cycles through these in order.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub enum Page {
+    /// Temperature, rates of change, status and time.
+    #[default]
+    Main,
+    /// Graph of the recent temperature, with its range and the current temperature.
+    Graph,
+    /// Peak, minimum and mean temperature and time above the threshold since the last reset.
+    Statistics,
+    /// Cold junction temperature, alert outputs and uptime.
+    Sensor,
+    /// Phase, target temperature and deviation of a reflow run.
+    Reflow,
+}
+
+impl Page {
+    /// The page after this one, wrapping around to the first.
+    pub fn next(self) -> Self {
+        match self {
+            Page::Main => Page::Graph,
+            Page::Graph => Page::Statistics,
+            Page::Statistics => Page::Sensor,
+            Page::Sensor => Page::Reflow,
+            Page::Reflow => Page::Main,
+        }
+    }
+}
+
+/// How the main page is laid out.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub enum Layout {
+    /// Temperature, rates of change, status and time.
+    #[default]
+    Normal,
+    /// Only the temperature, in seven segment digits the full height of a 32 row display.
+    Big,
+}
+
+/// Default number of decimals of the temperature on the main page, the sensor resolves 0.0625 C.
+pub const DEFAULT_DECIMALS: u8 = 4;
+/// The most decimals the temperature on the main page can be shown with.
+pub const MAX_DECIMALS: u8 = 4;
+
 #[derive(Default, Copy, Clone)]
 pub enum InternalStatus {
     Error,
     #[default]
     Good,
+    /// The last reading is older than the configured age, readings stopped arriving.
+    Stale,
 }
 
-#[derive(Default, Copy, Clone)]
+/// Half the period of blinking status icons, in milliseconds.
+pub const BLINK_MS: u32 = 500;
+
+/// State shown by the status icons, besides the alerts and the sensor status in the contents.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Indicators {
+    /// A host has the serial port open.
+    pub usb: bool,
+    /// Samples are being stored in the flash.
+    pub logging: bool,
+    /// The screensaver dimmed the display.
+    pub low_power: bool,
+    /// Blinking icons are hidden, for every other `BLINK_MS` of `clock::millis`.
+    pub blanked: bool,
+}
+
+impl Indicators {
+    /// Whether blinking icons are hidden at `now`.
+    pub fn blanked_at(now: u32) -> bool {
+        (now / BLINK_MS) % 2 == 1
+    }
+}
+
+#[derive(Copy, Clone)]
 pub struct Contents {
     /// The current temperature
     pub temperature: f32,
-    /// The temperature change in dC/s
+    /// The temperature change over the short window.
     pub avg_short: Change,
-    /// The temperature change in dC/s
+    /// The temperature change over the long window.
     pub avg_long: Change,
+    /// Unit the rates of change are shown in.
+    pub rate_unit: RateUnit,
+    /// Unit the temperatures are shown in.
+    pub unit: TemperatureUnit,
     /// The current time.
     pub time: u32,
     /// The internal status (reading success etc)
     pub status: InternalStatus,
+    /// Recent temperatures for the graph page.
+    pub graph: Sparkline<GRAPH_COLUMNS>,
+    /// Statistics since the last reset.
+    pub stats: Statistics,
+    /// The cold junction temperature.
+    pub cold_junction: f32,
+    /// State of the four alert outputs.
+    pub alerts: [bool; 4],
+    /// Decimals of the temperature on the main page, fewer are shown if it doesn't fit.
+    pub decimals: u8,
+    /// State of the status icons.
+    pub indicators: Indicators,
+    /// The reflow run in progress, if any.
+    pub run: Option<Run>,
+}
+impl Default for Contents {
+    fn default() -> Self {
+        Self {
+            temperature: 0.0,
+            avg_short: Default::default(),
+            avg_long: Default::default(),
+            rate_unit: Default::default(),
+            unit: Default::default(),
+            time: 0,
+            status: Default::default(),
+            graph: Default::default(),
+            stats: Default::default(),
+            cold_junction: 0.0,
+            alerts: [false; 4],
+            decimals: DEFAULT_DECIMALS,
+            indicators: Default::default(),
+            run: None,
+        }
+    }
 }
 impl Contents {
     pub fn test_contents() -> Self {
@@ -48,8 +162,17 @@ impl Contents {
                 duration: 9000,
                 temperature_delta: -41.347,
             },
+            rate_unit: RateUnit::PerSecond,
+            unit: TemperatureUnit::Celsius,
             time: 3600 * 1000 * 10,
             status: InternalStatus::Error,
+            graph: Default::default(),
+            stats: Default::default(),
+            cold_junction: 21.5,
+            alerts: [true, false, true, false],
+            decimals: DEFAULT_DECIMALS,
+            indicators: Default::default(),
+            run: None,
         }
     }
 }
@@ -64,24 +187,30 @@ impl core::fmt::Debug for Measurement {
         f.write_fmt(format_args!("{:?}", (self.time, self.temperature)))
     }
 }
-#[derive(Copy, Clone, Default, Debug, PartialEq)]
-pub struct Average {
-    buffer: [Measurement; 32],
+/// Ring buffer of recent measurements, holds `N - 1` measurements to determine the rate of change.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Average<const N: usize> {
+    buffer: [Measurement; N],
     index: usize,
 }
+impl<const N: usize> Default for Average<N> {
+    fn default() -> Self {
+        Self {
+            buffer: [Measurement::default(); N],
+            index: 0,
+        }
+    }
+}
 #[derive(Copy, Clone, Debug, PartialEq)]
-pub struct AverageIter<'a> {
-    average: &'a Average,
+pub struct AverageIter<'a, const N: usize> {
+    average: &'a Average<N>,
     our_index: usize,
 }
-impl<'a> Iterator for AverageIter<'a> {
+impl<'a, const N: usize> Iterator for AverageIter<'a, N> {
     type Item = Measurement;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let next_index = self
-            .our_index
-            .wrapping_sub(1)
-            .rem_euclid(self.average.buffer.len());
+        let next_index = (self.our_index + N - 1) % N;
         if next_index == self.average.index {
             None
         } else {
@@ -90,12 +219,110 @@ impl<'a> Iterator for AverageIter<'a> {
         }
     }
 }
+/// The unit temperatures are shown in, measurements are always kept in degrees Celsius.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+impl TemperatureUnit {
+    /// Convert a temperature in degrees Celsius.
+    pub fn convert(&self, celsius: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 1.8 + 32.0,
+            TemperatureUnit::Kelvin => celsius + 273.15,
+        }
+    }
+    /// Convert a temperature in this unit back to degrees Celsius.
+    pub fn to_celsius(&self, value: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => value,
+            TemperatureUnit::Fahrenheit => (value - 32.0) / 1.8,
+            TemperatureUnit::Kelvin => value - 273.15,
+        }
+    }
+    /// Convert a temperature difference in degrees Celsius, this only scales and has no offset.
+    pub fn convert_delta(&self, celsius: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius | TemperatureUnit::Kelvin => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 1.8,
+        }
+    }
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "C",
+            TemperatureUnit::Fahrenheit => "F",
+            TemperatureUnit::Kelvin => "K",
+        }
+    }
+}
+
+/// The unit a rate of change is shown in, the degrees follow the temperature unit.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub enum RateUnit {
+    /// Degrees per second, reflow profiles are specified in this.
+    #[default]
+    PerSecond,
+    PerMinute,
+    /// Degrees per hour, kiln schedules are specified in this.
+    PerHour,
+    /// Kelvin per minute, regardless of the temperature unit.
+    KelvinPerMinute,
+}
+impl RateUnit {
+    /// Number of seconds in the time unit.
+    pub fn seconds(&self) -> f32 {
+        match self {
+            RateUnit::PerSecond => 1.0,
+            RateUnit::PerMinute | RateUnit::KelvinPerMinute => 60.0,
+            RateUnit::PerHour => 3600.0,
+        }
+    }
+    /// The unit of the degrees, given the unit temperatures are shown in.
+    pub fn temperature_unit(&self, unit: TemperatureUnit) -> TemperatureUnit {
+        match self {
+            RateUnit::KelvinPerMinute => TemperatureUnit::Kelvin,
+            _ => unit,
+        }
+    }
+    /// Short label of the time unit for the display.
+    pub fn time_suffix(&self) -> &'static str {
+        match self {
+            RateUnit::PerSecond => "s",
+            RateUnit::PerMinute | RateUnit::KelvinPerMinute => "m",
+            RateUnit::PerHour => "h",
+        }
+    }
+    /// Decimals to show, such that typical rates fit in the same width.
+    pub fn decimals(&self) -> usize {
+        match self {
+            RateUnit::PerSecond => 2,
+            RateUnit::PerMinute | RateUnit::KelvinPerMinute => 1,
+            RateUnit::PerHour => 0,
+        }
+    }
+}
+
+/// How the rate of change is determined from the measurements in a window.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub enum RateEstimator {
+    /// Difference between the newest and the oldest measurement.
+    #[default]
+    Difference,
+    /// Slope of a linear fit through all measurements.
+    LeastSquares,
+}
+
 #[derive(Copy, Clone, Default, Debug, PartialEq)]
 pub struct Change {
     duration: u32,
     temperature_delta: f32,
 }
 impl Change {
+    /// The rate of change in degrees per second.
     pub fn to_rate(&self) -> f32 {
         if self.duration != 0 {
             self.temperature_delta / (self.duration as f32 / 1000.0)
@@ -103,6 +330,11 @@ impl Change {
             0.0
         }
     }
+    /// The rate of change in the provided units.
+    pub fn to_rate_in(&self, unit: RateUnit, temperature: TemperatureUnit) -> f32 {
+        unit.temperature_unit(temperature)
+            .convert_delta(self.to_rate() * unit.seconds())
+    }
     pub fn duration_ms(&self) -> u32 {
         self.duration
     }
@@ -119,13 +351,14 @@ impl Change {
     }
 }
 
-impl Average {
+impl<const N: usize> Average<N> {
     pub fn add_measurement(&mut self, time: u32, temperature: f32) {
         self.buffer[self.index].time = time;
         self.buffer[self.index].temperature = temperature;
         self.index = (self.index + 1) % self.buffer.len();
     }
-    pub fn get_average(&mut self, dt: u32) -> Change {
+    /// The change between the newest measurement and the first one at least `dt` older.
+    pub fn get_average(&self, dt: u32) -> Change {
         let mut iter = self.iter();
         let now = if let Some(first) = iter.next() {
             first
@@ -144,8 +377,61 @@ impl Average {
         }
         longest
     }
+
+    /// The change from a least squares fit over the measurements at most `dt` older than the
+    /// newest, this is much less affected by the quantization than the two point difference.
+    pub fn get_slope(&self, dt: u32) -> Change {
+        let Some(now) = self.iter().next() else {
+            return Default::default();
+        };
+        // Relative to the newest measurement, to retain precision in the sums.
+        let window = || {
+            self.iter()
+                .take_while(move |m| now.time.wrapping_sub(m.time) <= dt)
+                .map(move |m| {
+                    (
+                        m.time.wrapping_sub(now.time) as i32 as f32 / 1000.0,
+                        m.temperature - now.temperature,
+                    )
+                })
+        };
+
+        let (mut n, mut sum_t, mut sum_v, mut oldest) = (0u32, 0.0f32, 0.0f32, 0.0f32);
+        for (t, v) in window() {
+            n += 1;
+            sum_t += t;
+            sum_v += v;
+            oldest = t;
+        }
+        if n < 2 {
+            return Default::default();
+        }
+        let (mean_t, mean_v) = (sum_t / n as f32, sum_v / n as f32);
+        let (mut sxx, mut sxy) = (0.0f32, 0.0f32);
+        for (t, v) in window() {
+            sxx += (t - mean_t) * (t - mean_t);
+            sxy += (t - mean_t) * (v - mean_v);
+        }
+        if sxx == 0.0 {
+            return Default::default();
+        }
+        let duration = (-oldest * 1000.0 + 0.5) as u32;
+        Change {
+            duration,
+            temperature_delta: sxy / sxx * duration as f32 / 1000.0,
+        }
+    }
+
+    /// The change over `dt` using the provided estimator.
+    pub fn get_change(&self, dt: u32, estimator: RateEstimator) -> Change {
+        match estimator {
+            RateEstimator::Difference => self.get_average(dt),
+            RateEstimator::LeastSquares => self.get_slope(dt),
+        }
+    }
+
     /// Iterate over measurements, with least old first.
-    pub fn iter(&self) -> AverageIter {
+    pub fn iter(&self) -> AverageIter<'_, N> {
         AverageIter {
             average: self,
             our_index: self.index,
@@ -162,7 +448,7 @@ mod tests {
     use super::*;
     #[test]
     fn test_average_ring() {
-        let mut avg = Average::default();
+        let mut avg = Average::<32>::default();
         for i in 0..avg.buffer.len() {
             avg.add_measurement(i as u32, i as f32);
         }
@@ -186,7 +472,7 @@ mod tests {
         assert_eq!(change.duration_ms(), 3);
         assert_eq!(change.to_rate(), ((3.0) / (3.0 / 1000.0)));
 
-        let mut avg = Average::default();
+        let mut avg = Average::<32>::default();
         avg.add_measurement(0, 0.0);
         avg.add_measurement(0, 0.0);
         avg.add_measurement(0, 0.0);
@@ -212,32 +498,657 @@ mod tests {
             }
         }
     }
-}
 
-type Size = ssd1306::size::DisplaySize128x32;
+    #[test]
+    fn test_average_capacity() {
+        // Capacity that isn't a power of two wraps correctly.
+        let mut avg = Average::<5>::default();
+        for i in 0..12 {
+            avg.add_measurement(i * 1000, i as f32 * 2.0);
+        }
+        let times: Vec<u32> = avg.iter().map(|m| m.time).collect();
+        assert_eq!(times, [11000, 10000, 9000, 8000]);
+        // The window is limited by the capacity.
+        let change = avg.get_average(9000);
+        assert_eq!(change.duration_ms(), 3000);
+        assert_eq!(change.to_rate(), 2.0);
+        let celsius = TemperatureUnit::Celsius;
+        assert_eq!(change.to_rate_in(RateUnit::PerMinute, celsius), 120.0);
+        assert_eq!(change.to_rate_in(RateUnit::PerHour, celsius), 7200.0);
+        assert_eq!(change.to_rate_in(RateUnit::KelvinPerMinute, celsius), 120.0);
+    }
+
+    #[test]
+    fn test_indicators() {
+        assert!(!Indicators::blanked_at(0));
+        assert!(!Indicators::blanked_at(BLINK_MS - 1));
+        assert!(Indicators::blanked_at(BLINK_MS));
+        assert!(!Indicators::blanked_at(2 * BLINK_MS));
+
+        let mut c = Contents::default();
+        assert_eq!(indicator(&c, ICON_ALERT, false, true), None);
+        assert_eq!(indicator(&c, ICON_ALERT, true, true), Some(ICON_ALERT));
+        c.indicators.blanked = true;
+        assert_eq!(indicator(&c, ICON_ALERT, true, true), None);
+        assert_eq!(indicator(&c, ICON_USB, true, false), Some(ICON_USB));
+    }
+
+    #[test]
+    fn test_temperature_unit() {
+        assert_eq!(TemperatureUnit::Celsius.convert(21.5), 21.5);
+        assert_eq!(TemperatureUnit::Fahrenheit.convert(100.0), 212.0);
+        assert_eq!(TemperatureUnit::Fahrenheit.convert(-40.0), -40.0);
+        assert_eq!(TemperatureUnit::Kelvin.convert(-273.15), 0.0);
+        assert_eq!(TemperatureUnit::Fahrenheit.to_celsius(212.0), 100.0);
+        assert_eq!(TemperatureUnit::Kelvin.to_celsius(0.0), -273.15);
+
+        // Differences and rates scale, but have no offset.
+        assert_eq!(TemperatureUnit::Fahrenheit.convert_delta(10.0), 18.0);
+        assert_eq!(TemperatureUnit::Kelvin.convert_delta(10.0), 10.0);
+        let change = Change {
+            duration: 2000,
+            temperature_delta: 5.0,
+        };
+        let fahrenheit = TemperatureUnit::Fahrenheit;
+        assert_eq!(change.to_rate_in(RateUnit::PerSecond, fahrenheit), 4.5);
+        assert_eq!(change.to_rate_in(RateUnit::PerMinute, fahrenheit), 270.0);
+        // Kelvin per minute is fixed, even when showing Fahrenheit.
+        assert_eq!(
+            change.to_rate_in(RateUnit::KelvinPerMinute, fahrenheit),
+            150.0
+        );
+    }
+
+    /// Root mean square rate errors of the difference and least squares estimators on a quantized
+    /// noisy ramp.
+    fn ramp_errors(rate: f32, period: u32, window: u32) -> (f32, f32) {
+        let mut avg = Average::<64>::default();
+        let mut state: u32 = 0x1234567;
+        let (mut difference, mut least_squares) = (0.0f32, 0.0f32);
+        for i in 0..400 {
+            // Xorshift for uniform noise of +/- 0.1 C, then quantize like the sensor does.
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let noise = (state as f32 / 4294967296.0 - 0.5) * 0.2;
+            let time = i * period;
+            let v = 20.0 + rate * time as f32 / 1000.0 + noise;
+            avg.add_measurement(time, (v / 0.0625).floor() * 0.0625);
+            if i < 64 {
+                continue;
+            }
+            let d = avg.get_change(window, RateEstimator::Difference);
+            let l = avg.get_change(window, RateEstimator::LeastSquares);
+            assert_eq!(d.duration_ms(), window);
+            assert_eq!(l.duration_ms(), window);
+            difference += (d.to_rate() - rate).powi(2);
+            least_squares += (l.to_rate() - rate).powi(2);
+        }
+        let windows = (400 - 64) as f32;
+        (
+            (difference / windows).sqrt(),
+            (least_squares / windows).sqrt(),
+        )
+    }
+
+    #[test]
+    fn test_average_slope() {
+        // Exact on a clean ramp.
+        let mut avg = Average::<32>::default();
+        for i in 0..40 {
+            avg.add_measurement(i * 100, 10.0 + i as f32 * 0.25);
+        }
+        let change = avg.get_slope(1000);
+        assert_eq!(change.duration_ms(), 1000);
+        assert!((change.to_rate() - 2.5).abs() < 1e-4);
+
+        // Not enough measurements to fit.
+        let mut avg = Average::<4>::default();
+        for i in 0..3 {
+            avg.add_measurement(i * 100, 1.0);
+        }
+        assert_eq!(avg.get_slope(50), Change::default());
+        // No spread in time.
+        let mut avg = Average::<4>::default();
+        for v in [1.0, 2.0, 3.0] {
+            avg.add_measurement(100, v);
+        }
+        assert_eq!(avg.get_slope(1000), Change::default());
 
+        // For independent noise with deviation s on n samples spaced dt apart, the slope of the fit
+        // deviates by s * sqrt(12 / (n (n^2 - 1))) / dt, which is sqrt(n (n + 1) / (6 (n - 1)))
+        // times less than the difference does. The noise here is the uniform noise plus the
+        // quantization, s = sqrt((0.2^2 + 0.0625^2) / 12). The samples are quantized along a ramp
+        // and the windows overlap, so the errors follow the theory to within 20% only.
+        let s = ((0.2f32.powi(2) + 0.0625f32.powi(2)) / 12.0).sqrt();
+        for (rate, period) in [(1.0, 50), (-0.25, 50), (3.0, 100)] {
+            let (difference, least_squares) = ramp_errors(rate, period, 2000);
+            let n = (2000 / period + 1) as f32;
+            let expected = s * (12.0 / (n * (n * n - 1.0))).sqrt() / (period as f32 / 1000.0);
+            assert!(least_squares < expected * 1.2, "{rate} {least_squares}");
+            let gain = (n * (n + 1.0) / (6.0 * (n - 1.0))).sqrt();
+            assert!(
+                least_squares * gain * 0.8 < difference,
+                "{rate} {difference} {least_squares}"
+            );
+        }
+    }
+}
+
+use crate::controller::Controller;
 use crate::display_buffer::DeltaBuffer;
+use crate::widget::{Icon, Quantity, Widget, fitted};
+
+/// Width of the labels left of the graph.
+const GRAPH_LABEL_WIDTH: u32 = 32;
+
+/// Render a page on a display of `size`, with the main page in `layout`.
+///
+/// Displays less than 128 pixels wide use compact layouts, displays of 64 rows or more show the
+/// graph below the main and reflow pages and the sensor page below the statistics.
+fn content_render<DT: DrawTarget<Color = BinaryColor>>(
+    page: Page,
+    layout: Layout,
+    size: Size,
+    old_contents: Option<&Contents>,
+    new_contents: &Contents,
+    target: &mut DT,
+) -> Result<(), DT::Error> {
+    let full = Rectangle::new(Point::zero(), size);
+    let narrow = size.width < 128;
+    let (top, bottom) = if size.height >= 64 {
+        let half = Size::new(size.width, size.height / 2);
+        (
+            Rectangle::new(Point::zero(), half),
+            Some(Rectangle::new(Point::new(0, half.height as i32), half)),
+        )
+    } else {
+        (full, None)
+    };
+    match page {
+        Page::Main => {
+            match layout {
+                Layout::Normal => render_main(&top, narrow, old_contents, new_contents, target)?,
+                Layout::Big => render_big(&top, narrow, old_contents, new_contents, target)?,
+            }
+            if let Some(bottom) = bottom {
+                render_graph(&bottom, old_contents, new_contents, target)?;
+            }
+            Ok(())
+        }
+        Page::Graph => render_graph(&full, old_contents, new_contents, target),
+        Page::Statistics => {
+            render_statistics(&top, narrow, old_contents, new_contents, target)?;
+            if let Some(bottom) = bottom {
+                render_sensor(&bottom, narrow, old_contents, new_contents, target)?;
+            }
+            Ok(())
+        }
+        Page::Sensor => render_sensor(&full, narrow, old_contents, new_contents, target),
+        Page::Reflow => {
+            render_reflow(&top, narrow, old_contents, new_contents, target)?;
+            if let Some(bottom) = bottom {
+                render_graph(&bottom, old_contents, new_contents, target)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The current temperature in the unit it is shown in.
+fn temperature(c: &Contents) -> Quantity {
+    Quantity {
+        value: c.unit.convert(c.temperature),
+        decimals: c.decimals as usize,
+        unit: c.unit.suffix(),
+    }
+}
+
+/// Width of the status icons on the wide main page, the time is right of it.
+const STATUS_WIDTH: u32 = 40;
+
+const ICON_USB: Icon = [0x50, 0x50, 0xf8, 0xf8, 0x70, 0x20, 0x20, 0x00];
+const ICON_LOGGING: Icon = [0x38, 0x7c, 0xfe, 0xfe, 0xfe, 0x7c, 0x38, 0x00];
+const ICON_LOW_POWER: Icon = [0x38, 0x60, 0xc0, 0xc0, 0xc0, 0x60, 0x38, 0x00];
+const ICON_ALERT: Icon = [0x10, 0x38, 0x7c, 0x7c, 0x7c, 0xfe, 0x00, 0x10];
+const ICON_FAULT: Icon = [0x10, 0x28, 0x28, 0x54, 0x44, 0x92, 0xfe, 0x00];
+const ICON_STALE: Icon = [0xfe, 0x44, 0x28, 0x10, 0x28, 0x54, 0xfe, 0x00];
+
+/// `icon` if `on`, hidden every other `BLINK_MS` if `blink`.
+fn indicator(c: &Contents, icon: Icon, on: bool, blink: bool) -> Option<Icon> {
+    (on && !(blink && c.indicators.blanked)).then_some(icon)
+}
+
+fn fault_icon(c: &Contents) -> Option<Icon> {
+    match c.status {
+        InternalStatus::Error => indicator(c, ICON_FAULT, true, true),
+        InternalStatus::Stale => indicator(c, ICON_STALE, true, true),
+        InternalStatus::Good => None,
+    }
+}
+
+/// The host connection, logging, low power, alert and sensor fault or stale reading icons, the
+/// last two blink.
+const STATUS_ICONS: [Widget<'static>; 5] = [
+    Widget::icon(|c| indicator(c, ICON_USB, c.indicators.usb, false)),
+    Widget::icon(|c| indicator(c, ICON_LOGGING, c.indicators.logging, false)),
+    Widget::icon(|c| indicator(c, ICON_LOW_POWER, c.indicators.low_power, false)),
+    Widget::icon(|c| indicator(c, ICON_ALERT, c.alerts.contains(&true), true)),
+    Widget::icon(fault_icon),
+];
+
+fn render_main<DT: DrawTarget<Color = BinaryColor>>(
+    area: &Rectangle,
+    narrow: bool,
+    old_contents: Option<&Contents>,
+    new_contents: &Contents,
+    target: &mut DT,
+) -> Result<(), DT::Error> {
+    fn change(c: &Contents, rate: &Change) -> Result<crate::util::StackString, core::fmt::Error> {
+        crate::util::StackString::from_format(format_args!(
+            "{}s{} {}/{}",
+            rate.duration_s(),
+            fitted(
+                rate.to_rate_in(c.rate_unit, c.unit),
+                c.rate_unit.decimals(),
+                6
+            ),
+            c.rate_unit.temperature_unit(c.unit).suffix(),
+            c.rate_unit.time_suffix(),
+        ))
+    }
+
+    if narrow {
+        // Each rate on its own line, and the time below the status.
+        Widget::column(&[
+            Widget::number(&FONT_8X13_BOLD, 7, |c| Quantity {
+                decimals: (c.decimals as usize).min(2),
+                ..temperature(c)
+            }),
+            Widget::space(2),
+            Widget::text(&FONT_5X7, |c| change(c, &c.avg_long)),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| change(c, &c.avg_short)),
+            Widget::space(1),
+            Widget::row(&STATUS_ICONS),
+            Widget::space(1),
+            Widget::row(&[
+                Widget::label(&FONT_5X7, "t "),
+                Widget::number(&FONT_5X7, 9, |c| Quantity {
+                    value: c.time as f32 / 1000.0,
+                    decimals: 1,
+                    unit: "s",
+                }),
+            ]),
+        ])
+        .render(*area, old_contents, new_contents, target)
+    } else {
+        Widget::column(&[
+            Widget::row(&[
+                Widget::label(&FONT_8X13_BOLD, "T: "),
+                Widget::number(&FONT_8X13_BOLD, 11, temperature).spaced(),
+            ]),
+            Widget::space(2),
+            Widget::text(&FONT_5X7, |c| {
+                let decimals = c.rate_unit.decimals();
+                crate::util::StackString::from_format(format_args!(
+                    "{}s{} {}s{} {}/{}",
+                    c.avg_long.duration_s(),
+                    fitted(c.avg_long.to_rate_in(c.rate_unit, c.unit), decimals, 6),
+                    c.avg_short.duration_s(),
+                    fitted(c.avg_short.to_rate_in(c.rate_unit, c.unit), decimals, 6),
+                    c.rate_unit.temperature_unit(c.unit).suffix(),
+                    c.rate_unit.time_suffix(),
+                ))
+            }),
+            Widget::space(2),
+            Widget::row(&[
+                Widget::row(&STATUS_ICONS).width(STATUS_WIDTH),
+                Widget::label(&FONT_5X7, "t:  "),
+                Widget::number(&FONT_5X7, 10, |c| Quantity {
+                    value: c.time as f32 / 1000.0,
+                    decimals: 3,
+                    unit: "s",
+                })
+                .spaced(),
+            ]),
+        ])
+        .render(*area, old_contents, new_contents, target)
+    }
+}
+
+/// Characters of the big temperature, the unit and status take the remaining columns.
+const BIG_CHARS: usize = 7;
+const BIG_CHARS_NARROW: usize = 3;
+
+fn render_big<DT: DrawTarget<Color = BinaryColor>>(
+    area: &Rectangle,
+    narrow: bool,
+    old_contents: Option<&Contents>,
+    new_contents: &Contents,
+    target: &mut DT,
+) -> Result<(), DT::Error> {
+    let chars = if narrow { BIG_CHARS_NARROW } else { BIG_CHARS };
+    Widget::row(&[
+        Widget::number(&FONT_SEVEN_SEGMENT_16X32, chars, |c| Quantity {
+            unit: "",
+            ..temperature(c)
+        })
+        .width(chars as u32 * FONT_SEVEN_SEGMENT_16X32.character_size.width),
+        Widget::space(1),
+        Widget::column(&[
+            Widget::text(&FONT_6X10, |c| {
+                Ok(crate::util::StackString::from_str(c.unit.suffix()))
+            }),
+            Widget::fill(),
+            // Only a failure is shown, next to the bottom of the digits.
+            Widget::icon(fault_icon),
+        ]),
+    ])
+    .render(*area, old_contents, new_contents, target)
+}
+
+fn render_graph<DT: DrawTarget<Color = BinaryColor>>(
+    area: &Rectangle,
+    old_contents: Option<&Contents>,
+    new_contents: &Contents,
+    target: &mut DT,
+) -> Result<(), DT::Error> {
+    // Range labels on the left, top and bottom aligned with the graph.
+    Widget::row(&[
+        Widget::column(&[
+            Widget::text(&FONT_5X7, |c| match c.graph.range() {
+                Some((_, max)) => crate::util::StackString::from_format(format_args!(
+                    "{}",
+                    fitted(c.unit.convert(max), 1, 5)
+                )),
+                None => Ok(crate::util::StackString::from_str("")),
+            }),
+            Widget::fill(),
+            Widget::text(&FONT_5X7, |c| {
+                crate::util::StackString::from_format(format_args!(
+                    "T{}",
+                    fitted(c.unit.convert(c.temperature), 1, 5)
+                ))
+            }),
+            Widget::fill(),
+            Widget::text(&FONT_5X7, |c| match c.graph.range() {
+                Some((min, _)) => crate::util::StackString::from_format(format_args!(
+                    "{}",
+                    fitted(c.unit.convert(min), 1, 5)
+                )),
+                None => Ok(crate::util::StackString::from_str("")),
+            }),
+        ])
+        .width(GRAPH_LABEL_WIDTH),
+        Widget::sparkline(),
+    ])
+    .render(*area, old_contents, new_contents, target)
+}
+
+fn render_statistics<DT: DrawTarget<Color = BinaryColor>>(
+    area: &Rectangle,
+    narrow: bool,
+    old_contents: Option<&Contents>,
+    new_contents: &Contents,
+    target: &mut DT,
+) -> Result<(), DT::Error> {
+    fn extreme(
+        name: &str,
+        c: &Contents,
+        m: Option<Measurement>,
+    ) -> Result<crate::util::StackString, core::fmt::Error> {
+        match m {
+            Some(m) => crate::util::StackString::from_format(format_args!(
+                "{name} {} {} at {: >6} s",
+                fitted(c.unit.convert(m.temperature), 2, 7),
+                c.unit.suffix(),
+                m.time.wrapping_sub(c.stats.since()) / 1000
+            )),
+            None => crate::util::StackString::from_format(format_args!("{name} {: >7}", "-")),
+        }
+    }
+    /// Without the time, for narrow displays.
+    fn value(
+        name: &str,
+        c: &Contents,
+        v: Option<f32>,
+    ) -> Result<crate::util::StackString, core::fmt::Error> {
+        match v {
+            Some(v) => crate::util::StackString::from_format(format_args!(
+                "{name}{}{}",
+                fitted(c.unit.convert(v), 2, 8),
+                c.unit.suffix()
+            )),
+            None => crate::util::StackString::from_format(format_args!("{name}{: >8}", "-")),
+        }
+    }
+    // Four lines of the small font fill the display.
+    if narrow {
+        Widget::column(&[
+            Widget::text(&FONT_5X7, |c| {
+                value("max", c, c.stats.max().map(|m| m.temperature))
+            }),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| {
+                value("min", c, c.stats.min().map(|m| m.temperature))
+            }),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| value("avg", c, c.stats.mean())),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| {
+                crate::util::StackString::from_format(format_args!(
+                    ">{}{}{: >6}s",
+                    fitted(c.unit.convert(c.stats.threshold()), 0, 3),
+                    c.unit.suffix(),
+                    c.stats.time_above() / 1000
+                ))
+            }),
+        ])
+        .render(*area, old_contents, new_contents, target)
+    } else {
+        Widget::column(&[
+            Widget::text(&FONT_5X7, |c| extreme("max", c, c.stats.max())),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| extreme("min", c, c.stats.min())),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| match c.stats.mean() {
+                Some(v) => crate::util::StackString::from_format(format_args!(
+                    "mean {} {}",
+                    fitted(c.unit.convert(v), 2, 6),
+                    c.unit.suffix()
+                )),
+                None => crate::util::StackString::from_format(format_args!("mean {: >6}", "-")),
+            }),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| {
+                crate::util::StackString::from_format(format_args!(
+                    "above {} {} {: >7} s",
+                    fitted(c.unit.convert(c.stats.threshold()), 0, 5),
+                    c.unit.suffix(),
+                    c.stats.time_above() / 1000
+                ))
+            }),
+        ])
+        .render(*area, old_contents, new_contents, target)
+    }
+}
+
+fn render_sensor<DT: DrawTarget<Color = BinaryColor>>(
+    area: &Rectangle,
+    narrow: bool,
+    old_contents: Option<&Contents>,
+    new_contents: &Contents,
+    target: &mut DT,
+) -> Result<(), DT::Error> {
+    fn alerts(c: &Contents, prefix: &str) -> Result<crate::util::StackString, core::fmt::Error> {
+        let a = c.alerts.map(|v| if v { '1' } else { '0' });
+        crate::util::StackString::from_format(format_args!(
+            "{prefix} {} {} {} {}",
+            a[0], a[1], a[2], a[3]
+        ))
+    }
+    fn uptime(c: &Contents, prefix: &str) -> Result<crate::util::StackString, core::fmt::Error> {
+        let s = c.time / 1000;
+        crate::util::StackString::from_format(format_args!(
+            "{prefix}{: >4}:{:0>2}:{:0>2}",
+            s / 3600,
+            (s / 60) % 60,
+            s % 60
+        ))
+    }
+    fn cold_junction(c: &Contents, decimals: usize) -> Quantity {
+        Quantity {
+            value: c.unit.convert(c.cold_junction),
+            decimals,
+            unit: c.unit.suffix(),
+        }
+    }
+    if narrow {
+        Widget::column(&[
+            Widget::row(&[
+                Widget::label(&FONT_5X7, "cj"),
+                Widget::number(&FONT_5X7, 8, |c| cold_junction(c, 2)),
+            ]),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| alerts(c, "al")),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| uptime(c, "up")),
+        ])
+        .render(*area, old_contents, new_contents, target)
+    } else {
+        Widget::column(&[
+            Widget::row(&[
+                Widget::label(&FONT_6X10, "cold: "),
+                Widget::number(&FONT_6X10, 9, |c| cold_junction(c, 4)).spaced(),
+            ]),
+            Widget::space(1),
+            Widget::text(&FONT_6X10, |c| alerts(c, "alerts:")),
+            Widget::space(1),
+            Widget::text(&FONT_6X10, |c| uptime(c, "up: ")),
+        ])
+        .render(*area, old_contents, new_contents, target)
+    }
+}
+
+fn render_reflow<DT: DrawTarget<Color = BinaryColor>>(
+    area: &Rectangle,
+    narrow: bool,
+    old_contents: Option<&Contents>,
+    new_contents: &Contents,
+    target: &mut DT,
+) -> Result<(), DT::Error> {
+    fn phase(c: &Contents) -> Result<crate::util::StackString, core::fmt::Error> {
+        Ok(crate::util::StackString::from_str(
+            c.run.map_or("idle", |r| r.phase(c.time).name()),
+        ))
+    }
+    fn elapsed(c: &Contents) -> Result<crate::util::StackString, core::fmt::Error> {
+        match c.run {
+            Some(r) => crate::util::StackString::from_format(format_args!(
+                "{: >4}s",
+                r.elapsed(c.time) / 1000
+            )),
+            None => Ok(crate::util::StackString::from_str("")),
+        }
+    }
+    fn progress(c: &Contents) -> f32 {
+        c.run.map_or(0.0, |r| r.progress(c.time))
+    }
+    /// The target, or the deviation from it, in the shown unit; `-` outside of a run.
+    fn setpoint(
+        c: &Contents,
+        name: &str,
+        deviation: bool,
+    ) -> Result<crate::util::StackString, core::fmt::Error> {
+        match c.run.and_then(|r| r.target(c.time)) {
+            Some(t) if deviation => crate::util::StackString::from_format(format_args!(
+                "{name}{}",
+                fitted(c.unit.convert_delta(c.temperature - t), 1, 6)
+            )),
+            Some(t) => crate::util::StackString::from_format(format_args!(
+                "{name}{} {}",
+                fitted(c.unit.convert(t), 1, 6),
+                c.unit.suffix()
+            )),
+            None => crate::util::StackString::from_format(format_args!("{name}{: >6}", "-")),
+        }
+    }
+    fn temperature_1(c: &Contents) -> Quantity {
+        Quantity {
+            decimals: 1,
+            ..temperature(c)
+        }
+    }
+    if narrow {
+        Widget::column(&[
+            Widget::row(&[
+                Widget::text(&FONT_5X7, phase).width(39),
+                Widget::text(&FONT_5X7, elapsed),
+            ]),
+            Widget::space(1),
+            Widget::bar(progress).height(6),
+            Widget::space(1),
+            Widget::number(&FONT_8X13_BOLD, 6, temperature_1),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| setpoint(c, "set", false)),
+            Widget::space(1),
+            Widget::text(&FONT_5X7, |c| setpoint(c, "dev", true)),
+        ])
+        .render(*area, old_contents, new_contents, target)
+    } else {
+        Widget::column(&[
+            Widget::row(&[
+                Widget::text(&FONT_6X10, phase).width(48),
+                Widget::bar(progress).height(9),
+                Widget::space(4),
+                Widget::text(&FONT_6X10, elapsed).width(30),
+            ]),
+            Widget::space(1),
+            Widget::row(&[
+                Widget::label(&FONT_8X13_BOLD, "T "),
+                Widget::number(&FONT_8X13_BOLD, 7, temperature_1).spaced(),
+            ]),
+            Widget::space(1),
+            Widget::row(&[
+                Widget::text(&FONT_5X7, |c| setpoint(c, "set ", false)).width(60),
+                Widget::text(&FONT_5X7, |c| setpoint(c, "dev ", true)),
+            ]),
+        ])
+        .render(*area, old_contents, new_contents, target)
+    }
+}
 
-pub struct Display<DI: WriteOnlyDataCommand> {
-    display: Ssd1306<DI, Size, BasicMode>,
-    buffer: DeltaBuffer<Size>,
+pub struct Display<C: Controller> {
+    display: C,
+    buffer: DeltaBuffer<C::Size>,
     contents: Contents,
     old_contents: Contents,
+    page: Page,
+    layout: Layout,
+    saver: ScreenSaver,
+    /// The power state and layout offset currently applied to the display.
+    power: Power,
+    offset: Point,
 }
-impl<DI: WriteOnlyDataCommand> Display<DI> {
-    pub fn new(disp_int: DI) -> Self
-    where
-        DI: WriteOnlyDataCommand + Sized,
-    {
+impl<C: Controller> Display<C> {
+    pub fn new(display: C) -> Self {
         Self {
-            display: Ssd1306::new(disp_int, Size {}, DisplayRotation::Rotate0),
-            buffer: DeltaBuffer::<Size>::new(),
+            display,
+            buffer: DeltaBuffer::new(),
             old_contents: Default::default(),
             contents: Default::default(),
+            page: Default::default(),
+            layout: Default::default(),
+            saver: Default::default(),
+            power: Power::On,
+            offset: Point::zero(),
         }
     }
     pub fn init(&mut self) -> bool {
         if let Ok(()) = self.display.init() {
+            self.power = Power::On;
+            let _ = self.apply_power();
             self.buffer.clear_buffer();
             if let Ok(()) = self.buffer.flush(&mut self.display) {
                 return true;
@@ -246,118 +1157,134 @@ impl<DI: WriteOnlyDataCommand> Display<DI> {
         false
     }
 
+    /// The size of the display in pixels.
+    pub fn size() -> Size {
+        Size::new(C::Size::WIDTH.into(), C::Size::HEIGHT.into())
+    }
+
     pub fn contents_mut(&mut self) -> &mut Contents {
         &mut self.contents
     }
 
-    fn content_render<DT: embedded_graphics::draw_target::DrawTarget<Color = BinaryColor>>(
-        old_contents: &Contents,
-        new_contents: &Contents,
-        target: &mut DT,
-    ) -> Result<(), DT::Error> {
-        let text_style = MonoTextStyleBuilder::new()
-            .font(&FONT_5X7)
-            .text_color(BinaryColor::On)
-            .build();
-
-        let text_style_off = MonoTextStyleBuilder::new()
-            .font(&FONT_5X7)
-            .text_color(BinaryColor::Off)
-            .build();
-
-        let text_style_big = MonoTextStyleBuilder::new()
-            .font(&FONT_8X13_BOLD)
-            .text_color(BinaryColor::On)
-            .build();
-
-        let text_style_big_off = MonoTextStyleBuilder::new()
-            .font(&FONT_8X13_BOLD)
-            .text_color(BinaryColor::Off)
-            .build();
-
-        struct RenderSpec<'a> {
-            position: Point,
-            style: &'a MonoTextStyle<'a, BinaryColor>,
-            style_off: &'a MonoTextStyle<'a, BinaryColor>,
-            content: fn(&Contents) -> Result<crate::util::StackString, core::fmt::Error>,
-        }
-        let render_temp = RenderSpec {
-            position: Point::zero(),
-            style: &text_style_big,
-            style_off: &text_style_big_off,
-            content: |c: &Contents| {
-                crate::util::StackString::from_format(format_args!("T: {: >11.4} C", c.temperature))
-            },
-        };
+    /// The page currently shown.
+    pub fn page(&self) -> Page {
+        self.page
+    }
 
-        let render_change = RenderSpec {
-            position: Point::new(0, text_style_big.font.character_size.height as i32 + 2),
-            style: &text_style,
-            style_off: &text_style_off,
-            content: |c: &Contents| {
-                crate::util::StackString::from_format(format_args!(
-                    "dT/{}s {: >5.2} dT/{}s {: >5.2}",
-                    c.avg_long.duration_s(),
-                    c.avg_long.to_rate(),
-                    c.avg_short.duration_s(),
-                    c.avg_short.to_rate(),
-                ))
-            },
-        };
+    /// Switch to another page, this clears the screen and redraws the current contents.
+    pub fn set_page(&mut self, page: Page) -> Result<(), display_interface::DisplayError> {
+        if page == self.page {
+            return Ok(());
+        }
+        self.page = page;
+        self.redraw()
+    }
 
-        let render_time = RenderSpec {
-            position: Point::new(
-                30,
-                render_change.position.y + text_style.font.character_size.height as i32 + 2,
-            ),
-            style: &text_style,
-            style_off: &text_style_off,
-            content: |c: &Contents| {
-                crate::util::StackString::from_format(format_args!(
-                    "t:  {: >10.3} s",
-                    c.time as f32 / 1000.0
-                ))
-            },
-        };
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
 
-        let render_status = RenderSpec {
-            position: Point::new(
-                0,
-                render_change.position.y + text_style.font.character_size.height as i32 + 2,
-            ),
-            style: &text_style,
-            style_off: &text_style_off,
-            content: |c: &Contents| {
-                Ok(crate::util::StackString::from_str(match c.status {
-                    InternalStatus::Good => "ok",
-                    InternalStatus::Error => "fail",
-                }))
-            },
-        };
+    /// Switch the layout of the main page, this clears the screen and redraws the current contents.
+    pub fn set_layout(&mut self, layout: Layout) -> Result<(), display_interface::DisplayError> {
+        if layout == self.layout {
+            return Ok(());
+        }
+        self.layout = layout;
+        self.redraw()
+    }
 
-        for r in [render_temp, render_change, render_time, render_status] {
-            let old_res = (r.content)(old_contents);
-            let new_res = (r.content)(new_contents);
-            if old_res == new_res {
-                continue;
-            }
+    /// Clear the screen and redraw the current contents.
+    fn redraw(&mut self) -> Result<(), display_interface::DisplayError> {
+        self.buffer.clear_buffer();
+        let contents = self.old_contents;
+        content_render(
+            self.page,
+            self.layout,
+            Self::size(),
+            None,
+            &contents,
+            &mut self.buffer.translated(self.offset),
+        )
+    }
 
-            if let Ok(v) = old_res {
-                if let Ok(s) = v.as_str() {
-                    Text::with_baseline(s, r.position, *r.style_off, Baseline::Top).draw(target)?;
-                }
+    pub fn screensaver(&self) -> &screensaver::Config {
+        &self.saver.config
+    }
+
+    /// Change the screensaver configuration, this applies from the next service.
+    pub fn screensaver_mut(&mut self) -> &mut screensaver::Config {
+        &mut self.saver.config
+    }
+
+    /// Record activity, returns true if the display was dimmed or off; such that a button press
+    /// that only wakes the display can be ignored otherwise.
+    pub fn wake(&mut self, now: u32) -> Result<bool, display_interface::DisplayError> {
+        let was_on = self.power == Power::On;
+        self.saver.activity(now);
+        self.service(now)?;
+        Ok(!was_on)
+    }
+
+    fn apply_power(&mut self) -> Result<(), display_interface::DisplayError> {
+        let config = &self.saver.config;
+        match self.power {
+            Power::On => {
+                self.display.set_contrast(config.contrast)?;
+                self.display.set_display_on(true)
             }
-            if let Ok(v) = new_res {
-                if let Ok(s) = v.as_str() {
-                    Text::with_baseline(s, r.position, *r.style, Baseline::Top).draw(target)?;
-                }
+            Power::Dimmed => {
+                self.display.set_contrast(config.dim_contrast)?;
+                self.display.set_display_on(true)
             }
+            Power::Off => self.display.set_display_on(false),
+        }
+    }
+
+    /// Apply the screensaver; dim, switch off or shift the layout when it is time to.
+    pub fn service(&mut self, now: u32) -> Result<(), display_interface::DisplayError> {
+        let power = self.saver.power(now);
+        if power != self.power {
+            self.power = power;
+            self.apply_power()?;
+        }
+        let offset = self.saver.offset(now);
+        if offset != self.offset {
+            self.offset = offset;
+            self.redraw()?;
         }
         Ok(())
     }
 
+    /// Set the contrast when on, applied immediately.
+    pub fn set_contrast(&mut self, contrast: u8) -> Result<(), display_interface::DisplayError> {
+        self.saver.config.contrast = contrast;
+        self.apply_power()
+    }
+
+    /// Whether the screensaver dimmed the display.
+    pub fn is_dimmed(&self) -> bool {
+        self.power == Power::Dimmed
+    }
+
+    /// Whether the display is switched on, possibly dimmed.
+    pub fn is_on(&self) -> bool {
+        self.power != Power::Off
+    }
+
+    /// Advance to the next page.
+    pub fn next_page(&mut self) -> Result<(), display_interface::DisplayError> {
+        self.set_page(self.page.next())
+    }
+
     pub fn update(&mut self, contents: &Contents) -> Result<(), display_interface::DisplayError> {
-        Self::content_render(&self.old_contents, contents, &mut self.buffer)?;
+        content_render(
+            self.page,
+            self.layout,
+            Self::size(),
+            Some(&self.old_contents),
+            contents,
+            &mut self.buffer.translated(self.offset),
+        )?;
         self.old_contents = *contents;
         Ok(())
     }
@@ -380,51 +1307,283 @@ impl<DI: WriteOnlyDataCommand> Display<DI> {
 
 #[cfg(test)]
 mod more_tests {
+    //! Rendering compared against reference images in `snapshots/`, stored as plain PBM bitmaps.
+    //!
+    //! A missing reference fails the test like a differing one does. After an intended change of
+    //! the rendering, run the tests with `BLESS=1` to write the references, review and commit them.
     use super::*;
-    #[test]
-    fn test_render_image() {
-        use embedded_graphics::pixelcolor::Rgb888;
-        use embedded_graphics::prelude::*;
-        use embedded_graphics_simulator::{
-            BinaryColorTheme, OutputSettingsBuilder, SimulatorDisplay,
-        };
+    use embedded_graphics_simulator::SimulatorDisplay;
+    use std::path::PathBuf;
+
+    /// A page as shown on a display of a size.
+    #[derive(Copy, Clone, Debug)]
+    struct View {
+        name: &'static str,
+        page: Page,
+        layout: Layout,
+        size: Size,
+    }
+
+    const fn view(name: &'static str, page: Page, layout: Layout, size: (u32, u32)) -> View {
+        View {
+            name,
+            page,
+            layout,
+            size: Size::new(size.0, size.1),
+        }
+    }
 
-        let mut display = SimulatorDisplay::<BinaryColor>::new(Size::new(
-            DisplaySize128x32::WIDTH.into(),
-            DisplaySize128x32::HEIGHT.into(),
-        ));
-        let output_settings = OutputSettingsBuilder::new()
-            .pixel_spacing(1)
-            .scale(4)
-            .theme(BinaryColorTheme::Custom {
-                color_off: Rgb888::new(0, 0, 0),
-                color_on: Rgb888::new(0, 220, 255),
+    const VIEWS: [View; 13] = [
+        view("main", Page::Main, Layout::Normal, (128, 32)),
+        view("big", Page::Main, Layout::Big, (128, 32)),
+        view("graph", Page::Graph, Layout::Normal, (128, 32)),
+        view("stats", Page::Statistics, Layout::Normal, (128, 32)),
+        view("sensor", Page::Sensor, Layout::Normal, (128, 32)),
+        view("reflow", Page::Reflow, Layout::Normal, (128, 32)),
+        view("main", Page::Main, Layout::Normal, (128, 64)),
+        view("stats", Page::Statistics, Layout::Normal, (128, 64)),
+        view("main", Page::Main, Layout::Normal, (64, 48)),
+        view("big", Page::Main, Layout::Big, (64, 48)),
+        view("stats", Page::Statistics, Layout::Normal, (64, 48)),
+        view("sensor", Page::Sensor, Layout::Normal, (64, 48)),
+        view("reflow", Page::Reflow, Layout::Normal, (64, 48)),
+    ];
+
+    /// Contents with a graph and statistics of a ramp ending at `temperature`.
+    fn ramp(temperature: f32, step: f32) -> Contents {
+        let mut graph = Sparkline::new(5000);
+        let mut stats = Statistics::new(30.0);
+        for i in 0..GRAPH_COLUMNS as u32 + 4 {
+            let t = temperature - step * (GRAPH_COLUMNS as u32 + 3 - i) as f32;
+            graph.add(i * 5000, t);
+            stats.add(i * 5000, t);
+        }
+        Contents {
+            temperature,
+            avg_short: Change {
+                duration: 2000,
+                temperature_delta: step * 0.4,
+            },
+            avg_long: Change {
+                duration: 9000,
+                temperature_delta: step * 1.8,
+            },
+            time: 8843,
+            graph,
+            stats,
+            cold_junction: 21.5,
+            ..Default::default()
+        }
+    }
+
+    fn fixtures() -> [(&'static str, Contents); 8] {
+        [
+            ("normal", ramp(24.6875, 0.1)),
+            ("negative", ramp(-40.25, -0.5)),
+            (
+                "error",
+                Contents {
+                    status: InternalStatus::Error,
+                    ..ramp(24.6875, 0.1)
+                },
+            ),
+            (
+                "stale",
+                Contents {
+                    status: InternalStatus::Stale,
+                    ..ramp(24.6875, 0.1)
+                },
+            ),
+            // Everything as large as it gets, wider than the fields are meant for.
+            (
+                "overflow",
+                Contents {
+                    avg_short: Change {
+                        duration: 2000,
+                        temperature_delta: 123456.0,
+                    },
+                    time: u32::MAX,
+                    cold_junction: -1234.5678,
+                    ..ramp(1372.0, 14.0)
+                },
+            ),
+            (
+                "alert",
+                Contents {
+                    alerts: [true, true, false, true],
+                    indicators: Indicators {
+                        usb: true,
+                        logging: true,
+                        low_power: true,
+                        blanked: false,
+                    },
+                    ..ramp(24.6875, 0.1)
+                },
+            ),
+            // Halfway the soak, a few degrees below the target.
+            (
+                "reflow",
+                Contents {
+                    run: Some(Run::new(
+                        &crate::profile::TS391AX50,
+                        8843u32.wrapping_sub(75_000),
+                    )),
+                    ..ramp(122.5, 0.5)
+                },
+            ),
+            // The other half of the blink period, the alert icon is hidden.
+            (
+                "blanked",
+                Contents {
+                    alerts: [true, true, false, true],
+                    indicators: Indicators {
+                        usb: true,
+                        blanked: true,
+                        ..Default::default()
+                    },
+                    ..ramp(24.6875, 0.1)
+                },
+            ),
+        ]
+    }
+
+    /// Render `new`, over `old` if given, like the display is updated.
+    fn render(
+        view: &View,
+        old: Option<&Contents>,
+        new: &Contents,
+    ) -> SimulatorDisplay<BinaryColor> {
+        let mut display = SimulatorDisplay::new(view.size);
+        if let Some(old) = old {
+            content_render(view.page, view.layout, view.size, None, old, &mut display).unwrap();
+        }
+        content_render(view.page, view.layout, view.size, old, new, &mut display).unwrap();
+        display
+    }
+
+    /// Rows of the image, '1' for lit pixels like in a plain PBM.
+    fn rows(display: &SimulatorDisplay<BinaryColor>) -> Vec<String> {
+        let size = display.size();
+        (0..size.height as i32)
+            .map(|y| {
+                (0..size.width as i32)
+                    .map(|x| match display.get_pixel(Point::new(x, y)) {
+                        BinaryColor::On => '1',
+                        BinaryColor::Off => '0',
+                    })
+                    .collect()
             })
-            .build();
-        let mut old_contents: Contents = Contents::default();
-        let mut new_contents: Contents = Contents::test_contents();
-        if true {
-            new_contents.avg_long.duration = 8000;
-            new_contents.avg_long.temperature_delta = 2.74 * 8.0;
-            new_contents.avg_short.duration = 2000;
-            new_contents.avg_short.temperature_delta = -0.05 * 2.0;
-            new_contents.temperature = 24.6875;
-            new_contents.time = 8843;
-            old_contents.status = InternalStatus::Error;
-            new_contents.status = InternalStatus::Good;
-        }
-        Display::<ssd1306::test_helpers::StubInterface>::content_render(
-            &old_contents,
-            &new_contents,
-            &mut display,
-        )
-        .unwrap();
+            .collect()
+    }
+
+    fn parse_pbm(text: &str) -> Option<Vec<String>> {
+        let mut tokens = text.split_whitespace();
+        if tokens.next()? != "P1" {
+            return None;
+        }
+        let width: usize = tokens.next()?.parse().ok()?;
+        let height: usize = tokens.next()?.parse().ok()?;
+        let pixels: Vec<char> = tokens.flat_map(|t| t.chars()).collect();
+        if pixels.len() != width * height {
+            return None;
+        }
+        Some(pixels.chunks(width).map(|r| r.iter().collect()).collect())
+    }
 
-        let mut output_image = display.to_rgb_output_image(&output_settings);
-        let mut buffer = output_image.as_image_buffer();
+    fn to_pbm(rows: &[String]) -> String {
+        let width = rows.first().map_or(0, |r| r.len());
+        format!("P1\n{} {}\n{}\n", width, rows.len(), rows.join("\n"))
+    }
 
-        output_image
-            .save_png("/tmp/mcp9600_logger_render.png")
-            .unwrap();
+    /// The reference and the rendering overlaid, '#' is lit in both, '+' only in the rendering and
+    /// '-' only in the reference. Rows that differ are marked with a '>'.
+    fn diff(expected: &[String], actual: &[String]) -> String {
+        let mut out = String::new();
+        for (e, a) in expected.iter().zip(actual) {
+            let row: String = e
+                .chars()
+                .zip(a.chars())
+                .map(|p| match p {
+                    ('1', '1') => '#',
+                    ('0', '1') => '+',
+                    ('1', '0') => '-',
+                    _ => '.',
+                })
+                .collect();
+            out += if e == a { "  " } else { "> " };
+            out += &row;
+            out += "\n";
+        }
+        out
+    }
+
+    fn check_snapshot(name: &str, display: &SimulatorDisplay<BinaryColor>) -> Result<(), String> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("snapshots")
+            .join(format!("{name}.pbm"));
+        let actual = rows(display);
+        if std::env::var_os("BLESS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, to_pbm(&actual)).unwrap();
+            eprintln!("wrote {}", path.display());
+            return Ok(());
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                let expected = parse_pbm(&text)
+                    .ok_or_else(|| format!("{} isn't a plain PBM", path.display()))?;
+                if expected.len() != actual.len() || expected[0].len() != actual[0].len() {
+                    return Err(format!("{name} differs in size from {}", path.display()));
+                }
+                if expected != actual {
+                    return Err(format!(
+                        "{name} differs from {}:\n{}",
+                        path.display(),
+                        diff(&expected, &actual)
+                    ));
+                }
+            }
+            Err(e) => {
+                return Err(format!(
+                    "{name} has no reference {}, {e}, run with BLESS=1 to write it",
+                    path.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let mut failures = vec![];
+        for (fixture, contents) in fixtures() {
+            for view in VIEWS.iter() {
+                let name = format!(
+                    "{fixture}_{}_{}x{}",
+                    view.name, view.size.width, view.size.height
+                );
+                if let Err(e) = check_snapshot(&name, &render(view, None, &contents)) {
+                    failures.push(e);
+                }
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn test_incremental_render() {
+        for view in VIEWS.iter() {
+            for (old_name, old) in fixtures() {
+                for (new_name, new) in fixtures() {
+                    let fresh = rows(&render(view, None, &new));
+                    let updated = rows(&render(view, Some(&old), &new));
+                    assert!(
+                        fresh == updated,
+                        "{old_name} to {new_name} on {view:?}, '+' is left over, '-' missing:\n{}",
+                        diff(&fresh, &updated)
+                    );
+                }
+            }
+        }
     }
 }
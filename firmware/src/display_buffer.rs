@@ -1,28 +1,32 @@
 use core::ops::DerefMut;
+use core::ops::Range;
 
-use ssd1306::{
-    Ssd1306,
-    command::AddrMode,
-    mode::BasicMode,
-    rotation::DisplayRotation,
-    size::{DisplaySize, NewZeroed},
+use ssd1306::size::{
+    DisplaySize, DisplaySize64x48, DisplaySize128x32, DisplaySize128x64, NewZeroed,
 };
 
-use display_interface::{DisplayError, WriteOnlyDataCommand};
+use display_interface::DisplayError;
+
+use crate::controller::Controller;
 
 pub trait DeltaSize {
     const DELTASIZE: usize;
     type Buffer: AsMut<[u8]> + NewZeroed;
 }
-impl DeltaSize for ssd1306::size::DisplaySize128x32 {
-    const DELTASIZE: usize = (
-        ((ssd1306::size::DisplaySize128x32::WIDTH as usize
-            * ssd1306::size::DisplaySize128x32::HEIGHT as usize)
-            / 8)
-        // monochrome
-    ); // track delta per page.
-    type Buffer = [u8; Self::DELTASIZE];
+
+macro_rules! impl_delta_size {
+    ($($size:ty),*) => {
+        $(
+            impl DeltaSize for $size {
+                // monochrome, track delta per byte of a page.
+                const DELTASIZE: usize =
+                    (<$size>::WIDTH as usize * <$size>::HEIGHT as usize) / 8;
+                type Buffer = [u8; Self::DELTASIZE];
+            }
+        )*
+    };
 }
+impl_delta_size!(DisplaySize128x32, DisplaySize128x64, DisplaySize64x48);
 
 pub struct DeltaBuffer<SIZE>
 where
@@ -93,95 +97,63 @@ where
         }
     }
 
-    pub fn set_area<DI>(
-        &mut self,
-        display: &mut Ssd1306<DI, SIZE, BasicMode>,
-    ) -> Result<(), DisplayError>
+    /// Find the first run of dirty bytes at or after `from`, at most `max` long and within a page.
+    /// The run is marked clean.
+    fn next_run(&mut self, from: usize, max: usize) -> Option<Range<usize>> {
+        let width = SIZE::WIDTH as usize;
+        let delta = self.delta.as_mut();
+        let start = from + delta.get(from..)?.iter().position(|d| *d != 0)?;
+        let limit = ((start / width + 1) * width).min(start.saturating_add(max));
+        let end = delta[start..limit]
+            .iter()
+            .position(|d| *d == 0)
+            .map_or(limit, |p| start + p);
+        delta[start..end].fill(0);
+        Some(start..end)
+    }
+
+    fn send_run<C>(&mut self, display: &mut C, run: Range<usize>) -> Result<(), DisplayError>
     where
-        DI: WriteOnlyDataCommand,
+        C: Controller<Size = SIZE>,
     {
-        display.set_draw_area((0, 0), self.dimensions)
+        let width = SIZE::WIDTH as usize;
+        let (page, column) = ((run.start / width) as u8, (run.start % width) as u8);
+        display.draw_page(page, column, &self.buffer.as_mut()[run])
     }
 
     /// Write out data to a display.
     ///
-    /// This only updates the parts of the display that have changed since the last flush.
-    pub fn flush<DI>(
-        &mut self,
-        display: &mut Ssd1306<DI, SIZE, BasicMode>,
-    ) -> Result<(), DisplayError>
+    /// This only updates the parts of the display that have changed since the last flush, each
+    /// contiguous run of changed columns in a page is sent as a single write.
+    pub fn flush<C>(&mut self, display: &mut C) -> Result<(), DisplayError>
     where
-        DI: WriteOnlyDataCommand,
+        C: Controller<Size = SIZE>,
     {
-        let (width, height) = self.dimensions;
-
-        for y in (0..(height - 8)).step_by(8) {
-            //let mut dirty: bool = false;
-            for x in 0..width {
-                let (idx, _bit) = {
-                    let idx = ((y as usize) / 8 * SIZE::WIDTH as usize) + (x as usize);
-                    let bit = y % 8;
-
-                    (idx, bit)
-                };
-                let dirty = self.delta.as_mut()[idx] != 0;
-                self.delta.as_mut()[idx] = 0;
-
-                if dirty {
-                    //let idx = ((y as usize) / 8 * SIZE::WIDTH as usize) + (x as usize);
-                    let start = (x, y);
-                    let end = (x + 1, y | 7);
-                    display.set_draw_area(start, end)?;
-                    display.bounded_draw(&self.buffer.as_mut(), width as usize, start, end)?;
-                }
-            }
+        let mut position = 0;
+        while let Some(run) = self.next_run(position, usize::MAX) {
+            position = run.end;
+            self.send_run(display, run)?;
         }
-
+        self.update_position = 0;
         Ok(())
     }
 
-    /// Flush a partial number of blocks.
-    pub fn flush_partial<DI>(
-        &mut self,
-        display: &mut Ssd1306<DI, SIZE, BasicMode>,
-    ) -> Result<(), DisplayError>
+    /// Flush at most `blocks_per_update` blocks, continuing where the previous call stopped.
+    pub fn flush_partial<C>(&mut self, display: &mut C) -> Result<(), DisplayError>
     where
-        DI: WriteOnlyDataCommand,
+        C: Controller<Size = SIZE>,
     {
-        let (width, height) = self.dimensions;
-
-        // blocks_per_update
-        let mut send_blocks = 0;
-        for y in (0..(height)).step_by(8) {
-            for x in 0..width {
-                let (idx, _bit) = {
-                    let idx = ((y as usize) / 8 * SIZE::WIDTH as usize) + (x as usize);
-                    let bit = y % 8;
-
-                    (idx, bit)
-                };
-
-                if idx < self.update_position {
-                    continue;
-                }
-
-                let dirty = self.delta.as_mut()[idx] != 0;
-                self.delta.as_mut()[idx] = 0;
-
-                if dirty {
-                    let start = (x, y);
-                    let end = (x + 1, y | 7);
-                    display.set_draw_area(start, end)?;
-                    display.bounded_draw(&self.buffer.as_mut(), width as usize, start, end)?;
-                    send_blocks += 1;
-                    self.update_position = idx;
-                    if send_blocks >= self.blocks_per_update {
-                        return Ok(());
-                    }
-                }
-            }
+        let mut budget = self.blocks_per_update as usize;
+        while budget > 0 {
+            let Some(run) = self.next_run(self.update_position, budget) else {
+                // Reached the end, start from the top on the next call.
+                self.update_position = 0;
+                return Ok(());
+            };
+            budget -= run.len();
+            self.update_position = run.end;
+            self.send_run(display, run)?;
         }
-        self.update_position = 0;
         Ok(())
     }
 }
@@ -191,7 +163,7 @@ use embedded_graphics::{
     draw_target::DrawTarget,
     geometry::{Dimensions, OriginDimensions, Size},
     pixelcolor::BinaryColor,
-    primitives::Rectangle,
+    primitives::{PointsIter, Rectangle},
 };
 
 impl<SIZE> DrawTarget for DeltaBuffer<SIZE>
@@ -220,25 +192,28 @@ where
     where
         I: IntoIterator<Item = Self::Color>,
     {
-        let area = area.intersection(&self.bounding_box());
+        let bb = self.bounding_box();
         let mut pixels = pixels.into_iter();
 
         let buffer = self.buffer.as_mut();
 
-        for y in area.rows() {
-            for x in area.columns() {
-                let Some(color) = pixels.next() else {
-                    return Ok(());
-                };
-                let value = color.is_on() as u8;
-                let idx = ((y as usize) / 8 * SIZE::WIDTH as usize) + (x as usize);
-                let bit = y % 8;
-                let byte = &mut buffer[idx];
-                let previous = *byte;
-                *byte = *byte & !(1 << bit) | (value << bit);
-                if previous != *byte {
-                    self.delta.as_mut()[idx] = 1;
-                }
+        // The pixels are for the entire area, those outside of the display are skipped.
+        for point in area.points() {
+            let Some(color) = pixels.next() else {
+                return Ok(());
+            };
+            if !bb.contains(point) {
+                continue;
+            }
+            let (x, y) = (point.x as u32, point.y as u32);
+            let value = color.is_on() as u8;
+            let idx = ((y as usize) / 8 * SIZE::WIDTH as usize) + (x as usize);
+            let bit = y % 8;
+            let byte = &mut buffer[idx];
+            let previous = *byte;
+            *byte = *byte & !(1 << bit) | (value << bit);
+            if previous != *byte {
+                self.delta.as_mut()[idx] = 1;
             }
         }
 
@@ -247,6 +222,7 @@ where
 
     fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
         self.clear_impl(color.is_on());
+        self.delta.as_mut().fill(1);
         Ok(())
     }
 }
@@ -261,3 +237,162 @@ where
         Size::new(w.into(), h.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::Sh1106;
+    use crate::controller::tests::RecordingInterface;
+    use ssd1306::Ssd1306;
+    use ssd1306::mode::BasicMode;
+    use ssd1306::prelude::DisplayRotation;
+    use ssd1306::size::DisplaySize128x64;
+
+    /// A controller that records what it sends, the writes are read back from the commands.
+    trait Recording: Controller<Size = DisplaySize128x64> {
+        fn recording() -> Self;
+        fn finish(self) -> RecordingInterface;
+        /// The page, column and length of each write, from the commands that preceded it.
+        fn writes(i: &RecordingInterface) -> Vec<(u8, u8, usize)>;
+    }
+
+    impl Recording for Sh1106<RecordingInterface> {
+        fn recording() -> Self {
+            Sh1106::new(RecordingInterface::default())
+        }
+
+        fn finish(self) -> RecordingInterface {
+            self.release()
+        }
+
+        fn writes(i: &RecordingInterface) -> Vec<(u8, u8, usize)> {
+            i.data
+                .iter()
+                .map(|(n, data)| {
+                    let c = &i.commands[n - 3..*n];
+                    let column = (((c[2] & 0x0f) << 4) | c[1]) - 2;
+                    (c[0] & 0x0f, column, data.len())
+                })
+                .collect()
+        }
+    }
+
+    impl Recording for Ssd1306<RecordingInterface, DisplaySize128x64, BasicMode> {
+        fn recording() -> Self {
+            let mut d = Ssd1306::new(
+                RecordingInterface::default(),
+                DisplaySize128x64,
+                DisplayRotation::Rotate0,
+            );
+            Controller::init(&mut d).unwrap();
+            d
+        }
+
+        fn finish(self) -> RecordingInterface {
+            self.release()
+        }
+
+        fn writes(i: &RecordingInterface) -> Vec<(u8, u8, usize)> {
+            i.data
+                .iter()
+                .map(|(n, data)| {
+                    // The column and page window, which the data has to fill exactly.
+                    let c = &i.commands[n - 6..*n];
+                    assert_eq!((c[0], c[3]), (0x21, 0x22));
+                    assert_eq!(c[4], c[5]);
+                    assert_eq!(usize::from(c[2] - c[1]) + 1, data.len());
+                    (c[4], c[1], data.len())
+                })
+                .collect()
+        }
+    }
+
+    fn check_flush<C: Recording>() {
+        let mut buffer = DeltaBuffer::<DisplaySize128x64>::new();
+        let mut display = C::recording();
+
+        // The first and the last page, and a horizontal line that becomes one write.
+        buffer.set_pixel(0, 0, true);
+        buffer.set_pixel(127, 63, true);
+        for x in 10..20 {
+            buffer.set_pixel(x, 3, true);
+        }
+        buffer.set_pixel(20, 11, true);
+        buffer.flush(&mut display).unwrap();
+        let i = display.finish();
+        assert_eq!(
+            C::writes(&i),
+            [(0, 0, 1), (0, 10, 10), (1, 20, 1), (7, 127, 1)]
+        );
+        assert_eq!(i.data[3].1, [0x80]);
+
+        // Nothing changed, nothing sent.
+        let mut display = C::recording();
+        buffer.flush(&mut display).unwrap();
+        assert!(display.finish().data.is_empty());
+
+        // Runs are split at the page boundary, every page is sent in full.
+        let mut display = C::recording();
+        buffer.clear_buffer();
+        buffer.flush(&mut display).unwrap();
+        let i = display.finish();
+        let w = C::writes(&i);
+        assert_eq!(w.len(), 8);
+        assert!(w.iter().enumerate().all(|(p, w)| *w == (p as u8, 0, 128)));
+        assert_eq!(i.data.iter().map(|(_, d)| d.len()).sum::<usize>(), 1024);
+
+        // Clearing through the draw target marks everything dirty.
+        let mut display = C::recording();
+        buffer.clear(BinaryColor::On).unwrap();
+        buffer.flush(&mut display).unwrap();
+        let i = display.finish();
+        assert!(i.data.iter().all(|(_, d)| d.iter().all(|b| *b == 0xff)));
+        assert_eq!(i.data.iter().map(|(_, d)| d.len()).sum::<usize>(), 1024);
+    }
+
+    #[test]
+    fn test_flush() {
+        check_flush::<Ssd1306<RecordingInterface, DisplaySize128x64, BasicMode>>();
+        check_flush::<Sh1106<RecordingInterface>>();
+    }
+
+    fn check_flush_partial<C: Recording>() {
+        let mut buffer = DeltaBuffer::<DisplaySize128x64>::new();
+        buffer.set_blocks_per_update(10);
+        for x in 100..125 {
+            buffer.set_pixel(x, 62, true);
+        }
+        buffer.set_pixel(3, 0, true);
+
+        let flush_partial = |buffer: &mut DeltaBuffer<DisplaySize128x64>| {
+            let mut display = C::recording();
+            buffer.flush_partial(&mut display).unwrap();
+            C::writes(&display.finish())
+        };
+        assert_eq!(flush_partial(&mut buffer), [(0, 3, 1), (7, 100, 9)]);
+
+        // With the position in the last page, a pixel changed behind it is picked up after
+        // wrapping around, one ahead of it before that.
+        buffer.set_pixel(0, 0, true);
+        buffer.set_pixel(126, 62, true);
+        let mut sent = vec![];
+        for _ in 0..4 {
+            sent.push(flush_partial(&mut buffer));
+        }
+        assert_eq!(
+            sent,
+            [
+                vec![(7, 109, 10)],
+                vec![(7, 119, 6), (7, 126, 1)],
+                vec![(0, 0, 1)],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flush_partial() {
+        check_flush_partial::<Ssd1306<RecordingInterface, DisplaySize128x64, BasicMode>>();
+        check_flush_partial::<Sh1106<RecordingInterface>>();
+    }
+}
@@ -0,0 +1,179 @@
+//! Scrolling temperature graph for the display.
+//!
+//! The sparkline keeps one averaged value per column, the graph draws every pixel of its area on
+//! each render; the delta buffer only marks the bytes that actually changed, so only the columns
+//! that changed are sent to the display.
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+
+/// The graph never scales to less than this span in degrees, to not magnify the quantization.
+const MINIMUM_SPAN: f32 = 1.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sparkline<const N: usize> {
+    /// Column averages, the newest is at the end, only the last `len` are valid.
+    values: [f32; N],
+    len: usize,
+    /// Duration of each column in milliseconds.
+    column_ms: u32,
+    /// Start time of the newest column.
+    column_start: u32,
+    sum: f32,
+    count: u32,
+}
+
+impl<const N: usize> Sparkline<N> {
+    pub fn new(column_ms: u32) -> Self {
+        Self {
+            values: [0.0; N],
+            len: 0,
+            column_ms,
+            column_start: 0,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn column_ms(&self) -> u32 {
+        self.column_ms
+    }
+
+    /// Add a measurement, this is averaged into the newest column.
+    pub fn add(&mut self, time: u32, temperature: f32) {
+        if self.count > 0 && time.wrapping_sub(self.column_start) >= self.column_ms {
+            // Column complete, scroll and start the next one.
+            self.values.copy_within(1.., 0);
+            self.len = (self.len + 1).min(N);
+            self.count = 0;
+        }
+        if self.count == 0 {
+            self.column_start = time;
+            self.sum = 0.0;
+        }
+        self.sum += temperature;
+        self.count += 1;
+        self.values[N - 1] = self.sum / self.count as f32;
+        self.len = self.len.max(1);
+    }
+
+    /// The column values, oldest first.
+    pub fn values(&self) -> &[f32] {
+        &self.values[N - self.len..]
+    }
+
+    /// The range the graph is scaled to, None if there are no values.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        let values = self.values();
+        let first = *values.first()?;
+        let (min, max) = values
+            .iter()
+            .fold((first, first), |(min, max), v| (min.min(*v), max.max(*v)));
+        let expand = (MINIMUM_SPAN - (max - min)).max(0.0) / 2.0;
+        Some((min - expand, max + expand))
+    }
+
+    /// Draw the graph into `area`, newest column on the right. Every pixel in the area is drawn.
+    pub fn draw<DT: DrawTarget<Color = BinaryColor>>(
+        &self,
+        area: &Rectangle,
+        target: &mut DT,
+    ) -> Result<(), DT::Error> {
+        let width = area.size.width as usize;
+        let height = area.size.height as i32;
+        let values = self.values();
+        let values = &values[values.len().saturating_sub(width)..];
+        let (min, max) = self.range().unwrap_or((0.0, MINIMUM_SPAN));
+        let to_row = |v: f32| {
+            let fraction = ((v - min) / (max - min)).clamp(0.0, 1.0);
+            (height - 1) - (fraction * (height - 1) as f32 + 0.5) as i32
+        };
+
+        let first_column = width - values.len();
+        let mut previous: Option<i32> = None;
+        let pixels = (0..width).flat_map(|column| {
+            let span = if column < first_column {
+                None
+            } else {
+                let row = to_row(values[column - first_column]);
+                let from = previous.unwrap_or(row);
+                previous = Some(row);
+                Some((from.min(row), from.max(row)))
+            };
+            (0..height).map(move |row| {
+                let on = span.is_some_and(|(low, high)| row >= low && row <= high);
+                Pixel(
+                    area.top_left + Point::new(column as i32, row),
+                    BinaryColor::from(on),
+                )
+            })
+        });
+        target.draw_iter(pixels)
+    }
+}
+
+impl<const N: usize> Default for Sparkline<N> {
+    fn default() -> Self {
+        Self::new(5000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+    #[test]
+    fn test_sparkline() {
+        let mut s = Sparkline::<4>::new(100);
+        assert_eq!(s.range(), None);
+        s.add(0, 1.0);
+        s.add(50, 3.0);
+        assert_eq!(s.values(), &[2.0]);
+        s.add(100, 10.0);
+        assert_eq!(s.values(), &[2.0, 10.0]);
+        for i in 0..5 {
+            s.add(200 + i * 100, i as f32);
+        }
+        assert_eq!(s.values(), &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(s.range(), Some((1.0, 4.0)));
+
+        // Flat lines get a minimum span.
+        let mut s = Sparkline::<4>::new(100);
+        s.add(0, 20.0);
+        assert_eq!(s.range(), Some((19.5, 20.5)));
+    }
+
+    #[test]
+    fn test_sparkline_draw() {
+        let mut s = Sparkline::<8>::new(1);
+        for (i, v) in [0.0, 1.0, 2.0, 3.0, 0.0].iter().enumerate() {
+            s.add(i as u32, *v);
+        }
+        let mut display = MockDisplay::<BinaryColor>::new();
+        // Only the last four columns fit.
+        s.draw(
+            &Rectangle::new(Point::new(1, 0), Size::new(4, 4)),
+            &mut display,
+        )
+        .unwrap();
+        display.assert_pattern(&[
+            " ..##", //
+            " .###", //
+            " ##.#", //
+            " ...#", //
+        ]);
+
+        // Columns without data are cleared.
+        let mut s = Sparkline::<8>::new(1);
+        s.add(0, 5.0);
+        let mut display = MockDisplay::<BinaryColor>::new();
+        s.draw(
+            &Rectangle::new(Point::zero(), Size::new(3, 3)),
+            &mut display,
+        )
+        .unwrap();
+        display.assert_pattern(&[
+            "...", //
+            "..#", //
+            "...", //
+        ]);
+    }
+}
@@ -0,0 +1,426 @@
+//! Compact sample history, retained on the device while no host is reading the log.
+//!
+//! The first sample is stored in full, every later sample as a 16 bit time and temperature delta
+//! relative to the sample before it. A gap too long for a 16 bit time delta takes a second entry
+//! holding the full 32 bit delta. When the buffer fills up every other sample is dropped, and once
+//! that was possible for all of them the minimum interval between samples doubles, such that the
+//! history keeps covering the whole run at a lower resolution. Samples on either side of a delta
+//! that wouldn't get shorter by merging are kept. The timestamps of the retained samples are never
+//! altered.
+
+use crate::display::Measurement;
+// Temperatures are stored in the sensor's native resolution.
+use crate::mcp9600::TEMPERATURE_RESOLUTION as RESOLUTION;
+
+/// Time delta of an entry followed by a second entry with the full delta, for long gaps.
+const LONG: u16 = u16::MAX;
+
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+struct Entry {
+    /// Milliseconds since the previous sample, or LONG.
+    dt: u16,
+    /// Change in temperature since the previous sample, in RESOLUTION steps.
+    dtemp: i16,
+}
+
+/// Change from one sample to the next, stored in one entry or for long gaps in two.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Delta {
+    dt: u32,
+    dtemp: i16,
+}
+
+impl Delta {
+    /// Number of entries this is stored in.
+    fn entries(&self) -> usize {
+        if self.dt < LONG as u32 { 1 } else { 2 }
+    }
+
+    /// Merge two consecutive deltas, the result leads to the sample of `next`.
+    fn merge(&self, next: &Delta) -> Option<Delta> {
+        Some(Delta {
+            dt: self.dt.checked_add(next.dt)?,
+            dtemp: self.dtemp.wrapping_add(next.dtemp),
+        })
+    }
+}
+
+/// A sample as stored, time in milliseconds and temperature in RESOLUTION steps.
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+struct Sample {
+    time: u32,
+    raw: i16,
+}
+
+impl Sample {
+    fn advance(&self, delta: &Delta) -> Sample {
+        Sample {
+            time: self.time.wrapping_add(delta.dt),
+            raw: self.raw.wrapping_add(delta.dtemp),
+        }
+    }
+    fn to_measurement(self) -> Measurement {
+        Measurement {
+            time: self.time,
+            temperature: self.raw as f32 * RESOLUTION,
+        }
+    }
+}
+
+pub struct History<const N: usize> {
+    /// The oldest sample, the first entry is relative to this.
+    first: Option<Sample>,
+    /// The newest sample, the next entry will be relative to this.
+    last: Sample,
+    entries: [Entry; N],
+    /// Number of entries in use, long gaps take two.
+    len: usize,
+    /// Minimum interval between stored samples, doubles with every decimation.
+    interval: u32,
+    /// Incremented whenever existing entries change, this invalidates cursors.
+    generation: u32,
+}
+
+/// Position in the history, used to read it out a bit at a time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HistoryCursor {
+    index: usize,
+    sample: Option<Sample>,
+    generation: u32,
+}
+
+impl<const N: usize> History<N> {
+    /// Create a history storing at most one sample per `interval` milliseconds initially.
+    pub fn new(interval: u32) -> Self {
+        Self {
+            first: None,
+            last: Default::default(),
+            entries: [Default::default(); N],
+            len: 0,
+            interval,
+            generation: 0,
+        }
+    }
+
+    /// Number of samples in the history.
+    pub fn len(&self) -> usize {
+        let mut len = self.first.is_some() as usize;
+        let mut index = 0;
+        while index < self.len {
+            index += self.delta(index).entries();
+            len += 1;
+        }
+        len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.first.is_none()
+    }
+
+    /// The current minimum interval between samples in milliseconds.
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// Remove all samples, the interval is kept.
+    pub fn clear(&mut self) {
+        self.first = None;
+        self.len = 0;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Add a sample, this is dropped if it is within the interval of the previous one.
+    pub fn add(&mut self, time: u32, temperature: f32) {
+        let sample = Sample {
+            time,
+            raw: (temperature / RESOLUTION) as i16,
+        };
+        if self.first.is_none() {
+            self.first = Some(sample);
+            self.last = sample;
+            return;
+        }
+
+        let dt = time.wrapping_sub(self.last.time);
+        if dt < self.interval {
+            return;
+        }
+        self.push(Delta {
+            dt,
+            dtemp: sample.raw.wrapping_sub(self.last.raw),
+        });
+        self.last = sample;
+    }
+
+    fn push(&mut self, delta: Delta) {
+        while self.len + delta.entries() > N {
+            self.decimate();
+        }
+        self.len += self.put(self.len, delta);
+    }
+
+    /// The delta stored at entry `index`.
+    fn delta(&self, index: usize) -> Delta {
+        let entry = self.entries[index];
+        let dt = if entry.dt == LONG {
+            let full = self.entries[index + 1];
+            ((full.dt as u32) << 16) | full.dtemp as u16 as u32
+        } else {
+            entry.dt as u32
+        };
+        Delta {
+            dt,
+            dtemp: entry.dtemp,
+        }
+    }
+
+    /// Store `delta` at entry `index`, returns the number of entries used.
+    fn put(&mut self, index: usize, delta: Delta) -> usize {
+        if delta.entries() == 1 {
+            self.entries[index] = Entry {
+                dt: delta.dt as u16,
+                dtemp: delta.dtemp,
+            };
+        } else {
+            self.entries[index] = Entry {
+                dt: LONG,
+                dtemp: delta.dtemp,
+            };
+            self.entries[index + 1] = Entry {
+                dt: (delta.dt >> 16) as u16,
+                dtemp: delta.dt as u16 as i16,
+            };
+        }
+        delta.entries()
+    }
+
+    /// Drop every other sample, except where merging the deltas wouldn't free an entry. If that
+    /// frees nothing, the oldest half of the entries is dropped instead. The interval only doubles
+    /// once every other sample could be dropped, until then parts of the history are finer.
+    fn decimate(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        // Entries only move towards the front, never past the pair being read.
+        let (mut read, mut len) = (0, 0);
+        let mut all = true;
+        while read < self.len {
+            let a = self.delta(read);
+            read += a.entries();
+            if read == self.len {
+                len += self.put(len, a);
+                break;
+            }
+            let b = self.delta(read);
+            read += b.entries();
+            match a.merge(&b) {
+                Some(merged) if merged.entries() < a.entries() + b.entries() => {
+                    len += self.put(len, merged);
+                }
+                _ => {
+                    len += self.put(len, a);
+                    len += self.put(len, b);
+                    all = false;
+                }
+            }
+        }
+        if len < self.len {
+            self.len = len;
+            if all {
+                self.interval = self.interval.saturating_mul(2).max(1);
+            }
+        } else if let Some(mut first) = self.first {
+            let mut dropped = 0;
+            while dropped < self.len / 2 {
+                let delta = self.delta(dropped);
+                first = first.advance(&delta);
+                dropped += delta.entries();
+            }
+            self.first = Some(first);
+            self.entries.copy_within(dropped..self.len, 0);
+            self.len -= dropped;
+        }
+    }
+
+    /// Cursor at the oldest sample.
+    pub fn cursor(&self) -> HistoryCursor {
+        HistoryCursor {
+            index: 0,
+            sample: None,
+            generation: self.generation,
+        }
+    }
+
+    /// False if the history was decimated or cleared since the cursor was created.
+    pub fn is_valid(&self, cursor: &HistoryCursor) -> bool {
+        cursor.generation == self.generation
+    }
+
+    /// Advance the cursor, returning the next sample.
+    pub fn next(&self, cursor: &mut HistoryCursor) -> Option<Measurement> {
+        if !self.is_valid(cursor) {
+            return None;
+        }
+        let sample = match cursor.sample {
+            None => self.first?,
+            Some(previous) => {
+                if cursor.index >= self.len {
+                    return None;
+                }
+                let delta = self.delta(cursor.index);
+                cursor.index += delta.entries();
+                previous.advance(&delta)
+            }
+        };
+        cursor.sample = Some(sample);
+        Some(sample.to_measurement())
+    }
+
+    /// Iterate over the samples, oldest first.
+    pub fn iter(&self) -> HistoryIter<'_, N> {
+        HistoryIter {
+            history: self,
+            cursor: self.cursor(),
+        }
+    }
+}
+
+pub struct HistoryIter<'a, const N: usize> {
+    history: &'a History<N>,
+    cursor: HistoryCursor,
+}
+
+impl<const N: usize> Iterator for HistoryIter<'_, N> {
+    type Item = Measurement;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.history.next(&mut self.cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn m(time: u32, temperature: f32) -> Measurement {
+        Measurement { time, temperature }
+    }
+    #[test]
+    fn test_history() {
+        let mut h = History::<4>::new(100);
+        assert!(h.is_empty());
+        assert_eq!(h.iter().next(), None);
+        h.add(1000, 20.0);
+        h.add(1050, 21.0); // within interval, dropped.
+        h.add(1100, 20.5);
+        h.add(1250, -3.0625);
+        assert_eq!(h.len(), 3);
+        let v: Vec<_> = h.iter().collect();
+        assert_eq!(v, vec![m(1000, 20.0), m(1100, 20.5), m(1250, -3.0625)]);
+
+        // Fill it up, the next one decimates.
+        h.add(1400, 1.0);
+        h.add(1500, 2.0);
+        assert_eq!(h.len(), 5);
+        let cursor = h.cursor();
+        h.add(1600, 3.0);
+        assert!(!h.is_valid(&cursor));
+        assert_eq!(h.interval(), 200);
+        let v: Vec<_> = h.iter().collect();
+        assert_eq!(
+            v,
+            vec![m(1000, 20.0), m(1250, -3.0625), m(1500, 2.0), m(1600, 3.0)]
+        );
+        // Interval is now doubled.
+        h.add(1700, 4.0);
+        assert_eq!(h.len(), 4);
+        h.add(1800, 5.0);
+        assert_eq!(h.len(), 5);
+
+        h.clear();
+        assert!(h.is_empty());
+        assert_eq!(h.iter().next(), None);
+    }
+
+    #[test]
+    fn test_history_cursor() {
+        let mut h = History::<8>::new(0);
+        h.add(0, 1.0);
+        h.add(10, 2.0);
+        let mut cursor = h.cursor();
+        assert_eq!(h.next(&mut cursor), Some(m(0, 1.0)));
+        assert_eq!(h.next(&mut cursor), Some(m(10, 2.0)));
+        assert_eq!(h.next(&mut cursor), None);
+        // Samples added after the cursor reached the end are still returned.
+        h.add(20, 3.0);
+        assert_eq!(h.next(&mut cursor), Some(m(20, 3.0)));
+        assert!(h.is_valid(&cursor));
+    }
+
+    #[test]
+    fn test_history_wrapping_and_gaps() {
+        let mut h = History::<8>::new(0);
+        // Time wraps around, large temperature swing.
+        h.add(u32::MAX - 10, -2000.0);
+        h.add(20, 2000.0);
+        // Gap longer than a delta can express.
+        h.add(200_020, 10.0);
+        // No samples are made up in the gap.
+        let v: Vec<_> = h.iter().collect();
+        assert_eq!(
+            v,
+            vec![m(u32::MAX - 10, -2000.0), m(20, 2000.0), m(200_020, 10.0)]
+        );
+        assert_eq!(h.len(), 3);
+    }
+
+    #[test]
+    fn test_history_long_gap() {
+        // The samples around a gap too long to merge are kept, the others are still merged.
+        let mut h = History::<4>::new(0);
+        for time in [0, 100, 200, 40_200, 80_200] {
+            h.add(time, time as f32 / 1000.0);
+        }
+        h.add(80_300, 80.0);
+        let v: Vec<_> = h.iter().collect();
+        assert_eq!(
+            v,
+            vec![
+                m(0, 0.0),
+                m(200, 0.1875),
+                m(40_200, 40.1875),
+                m(80_200, 80.1875),
+                m(80_300, 80.0)
+            ]
+        );
+        // Not all samples could be dropped, the interval stays.
+        assert_eq!(h.interval(), 0);
+
+        // Long gaps merge into a single long gap.
+        let mut h = History::<4>::new(0);
+        for time in [0, 100_000, 200_000, 200_100] {
+            h.add(time, time as f32 / 1000.0);
+        }
+        let v: Vec<_> = h.iter().collect();
+        assert_eq!(v, vec![m(0, 0.0), m(200_000, 200.0), m(200_100, 200.0625)]);
+        assert_eq!(h.interval(), 1);
+    }
+
+    #[test]
+    fn test_history_drop_oldest() {
+        // None of the deltas can be merged, the oldest half is dropped instead.
+        let mut h = History::<4>::new(0);
+        for i in 0..5 {
+            h.add(i * 40_000, i as f32);
+        }
+        assert_eq!(h.len(), 5);
+        h.add(5 * 40_000, 5.0);
+        let v: Vec<_> = h.iter().collect();
+        assert_eq!(
+            v,
+            vec![
+                m(2 * 40_000, 2.0),
+                m(3 * 40_000, 3.0),
+                m(4 * 40_000, 4.0),
+                m(5 * 40_000, 5.0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,609 @@
+#![allow(unsafe_code)]
+//! Display writes on I2C2, sent in the background with DMA.
+//!
+//! Writes to the display are queued as frames, one I2C transfer each, and DMA1 channel 4 feeds
+//! them to I2C2. The I2C event interrupt sends the address and, once the data is out, the stop
+//! before starting the next frame. The main loop only copies the bytes into the queue, instead of
+//! waiting 25 us for every byte at 400 kHz.
+//!
+//! When a transfer fails, or the queue doesn't drain, the DMA is no longer used. The frames still
+//! queued and all later writes are then sent with the blocking driver.
+//!
+//! The panic handler can't wait for interrupts, nor take the driver from whoever holds it. It
+//! writes through the registers directly instead, see `panic_interface`.
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use cortex_m::interrupt::Mutex;
+use cortex_m::peripheral::NVIC;
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+use embedded_hal::i2c::I2c;
+use stm32f1xx_hal::dma::dma1::C4;
+use stm32f1xx_hal::i2c::BlockingI2c;
+use stm32f1xx_hal::pac::{I2C2, Interrupt, i2c1, interrupt};
+
+/// Address of the display controller, the SSD1306 and SH1106 use the same.
+pub const DISPLAY_ADDR: u8 = 0x3c;
+
+/// Fits the position commands and a page of the SH1106, which has 132 columns.
+const FRAME_CAPACITY: usize = 144;
+const QUEUE_LEN: usize = 4;
+/// Waiting longer than this for room in the queue is treated as a failure, in milliseconds.
+const TIMEOUT_MS: u32 = 100;
+/// Polls of a status flag before the panic interface gives up, the clock may not be running.
+const PANIC_TIMEOUT_POLLS: u32 = 100_000;
+/// Polls of the stop bit in the event interrupt before the bus is considered stuck. The stop
+/// condition takes a bit time, 2.5 us at 400 kHz, a poll takes at least a few cycles at 48 MHz.
+const STOP_POLLS: u32 = 1000;
+
+/// Control byte followed by a single command, and then another control byte.
+const CONTROL_COMMAND: u8 = 0x80;
+/// Control byte after which the rest of the transfer is data.
+const CONTROL_DATA: u8 = 0x40;
+
+/// A single transfer; commands, each with their own control byte, optionally followed by data.
+#[derive(Copy, Clone, Debug)]
+struct Frame {
+    len: usize,
+    /// Data was added, which has to be the end of the transfer.
+    closed: bool,
+    bytes: [u8; FRAME_CAPACITY],
+}
+
+impl Frame {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            closed: false,
+            bytes: [0; FRAME_CAPACITY],
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Append as many commands as fit, returns the number taken.
+    fn push_commands(&mut self, commands: &[u8]) -> usize {
+        if self.closed {
+            return 0;
+        }
+        let count = commands.len().min((FRAME_CAPACITY - self.len) / 2);
+        for c in &commands[..count] {
+            self.bytes[self.len] = CONTROL_COMMAND;
+            self.bytes[self.len + 1] = *c;
+            self.len += 2;
+        }
+        count
+    }
+
+    /// Append as much data as fits, returns the number of bytes taken.
+    fn push_data(&mut self, data: &[u8]) -> usize {
+        if self.closed || data.is_empty() || FRAME_CAPACITY - self.len < 2 {
+            return 0;
+        }
+        self.bytes[self.len] = CONTROL_DATA;
+        self.len += 1;
+        let count = data.len().min(FRAME_CAPACITY - self.len);
+        self.bytes[self.len..self.len + count].copy_from_slice(&data[..count]);
+        self.len += count;
+        self.closed = true;
+        count
+    }
+}
+
+type PushFn = fn(&mut Frame, &[u8]) -> usize;
+
+#[derive(Debug)]
+struct Queue {
+    frames: [Frame; QUEUE_LEN],
+    /// Index of the oldest frame, which is the one being sent when `active`.
+    first: usize,
+    count: usize,
+    active: bool,
+    /// Time the oldest frame started being sent, in milliseconds.
+    started: u32,
+}
+
+impl Queue {
+    const fn new() -> Self {
+        Self {
+            frames: [Frame::new(); QUEUE_LEN],
+            first: 0,
+            count: 0,
+            active: false,
+            started: 0,
+        }
+    }
+
+    /// Add bytes with `push`, to the last frame if it isn't being sent yet. Returns the number of
+    /// bytes taken, zero if the queue is full.
+    fn push(&mut self, bytes: &[u8], push: PushFn) -> usize {
+        let sending_last = self.active && self.count == 1;
+        if self.count > 0 && !sending_last {
+            let last = (self.first + self.count - 1) % QUEUE_LEN;
+            let taken = push(&mut self.frames[last], bytes);
+            if taken > 0 {
+                return taken;
+            }
+        }
+        if self.count == QUEUE_LEN {
+            return 0;
+        }
+        let next = (self.first + self.count) % QUEUE_LEN;
+        self.frames[next] = Frame::new();
+        self.count += 1;
+        push(&mut self.frames[next], bytes)
+    }
+
+    fn front(&self) -> Option<&Frame> {
+        (self.count != 0).then(|| &self.frames[self.first])
+    }
+
+    /// Remove the oldest frame, after it was sent.
+    fn pop(&mut self) {
+        if self.count != 0 {
+            self.first = (self.first + 1) % QUEUE_LEN;
+            self.count -= 1;
+        }
+        self.active = false;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The oldest frame is being sent from `now`.
+    fn begin(&mut self, now: u32) {
+        self.active = true;
+        self.started = now;
+    }
+
+    /// The oldest frame wasn't sent, it stays queued to be sent again.
+    fn abort(&mut self) {
+        self.active = false;
+    }
+
+    /// Whether the frame being sent should have been out long before `now`.
+    fn stalled(&self, now: u32) -> bool {
+        self.active && now.wrapping_sub(self.started) > TIMEOUT_MS
+    }
+
+    /// Whether writes can be queued without waiting for room. Once the DMA path `failed` that is
+    /// always the case, the next write moves the queue over to the blocking driver.
+    fn is_idle(&self, failed: bool) -> bool {
+        failed || self.is_empty()
+    }
+
+    /// Send all queued frames with the blocking driver, returns the first error.
+    fn drain(&mut self, i2c: &mut impl I2c) -> Result<(), DisplayError> {
+        let mut result = Ok(());
+        while let Some(frame) = self.front() {
+            if i2c.write(DISPLAY_ADDR, frame.bytes()).is_err() {
+                result = Err(DisplayError::BusWriteError);
+            }
+            self.pop();
+        }
+        result
+    }
+}
+
+struct Transfers {
+    queue: Queue,
+    i2c: BlockingI2c<I2C2>,
+    dma: C4,
+}
+
+static GLOBAL_TRANSFERS: Mutex<RefCell<Option<Transfers>>> = Mutex::new(RefCell::new(None));
+static FAILED: AtomicBool = AtomicBool::new(false);
+/// Set once the I2C peripheral is configured.
+static CONFIGURED: AtomicBool = AtomicBool::new(false);
+
+fn regs() -> &'static i2c1::RegisterBlock {
+    // Only used while the transfers own the peripheral, through the blocking driver, or once the
+    // panic handler took it over.
+    unsafe { &*I2C2::ptr() }
+}
+
+impl Transfers {
+    /// Start sending the oldest frame, if the bus is idle.
+    fn start(&mut self) {
+        if self.queue.active || FAILED.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(frame) = self.queue.front() else {
+            return;
+        };
+        self.dma
+            .set_memory_address(frame.bytes.as_ptr() as u32, true);
+        self.dma.set_transfer_length(frame.len);
+        self.dma.start();
+        self.queue.begin(crate::clock::millis());
+        // The event interrupt continues once the start condition is sent.
+        regs().cr1().modify(|_, w| w.start().set_bit());
+    }
+
+    fn event(&mut self) {
+        let i2c = regs();
+        let sr1 = i2c.sr1().read();
+        if sr1.sb().bit_is_set() {
+            // Writing the address after reading SR1 clears the start flag.
+            i2c.dr()
+                .write(|w| unsafe { w.bits(u32::from(DISPLAY_ADDR) << 1) });
+        } else if sr1.addr().bit_is_set() {
+            // Reading SR2 after SR1 clears the address flag, the DMA then feeds the data register.
+            let _ = i2c.sr2().read();
+        } else if sr1.btf().bit_is_set() && self.dma.get_ndtr() == 0 {
+            // The last byte is out.
+            i2c.cr1().modify(|_, w| w.stop().set_bit());
+            self.dma.stop();
+            self.queue.pop();
+            // A new start is only generated after the stop condition.
+            if poll_at_most(STOP_POLLS, || i2c.cr1().read().stop().bit_is_clear()).is_err() {
+                // The bus is stuck, leave the rest to the blocking driver.
+                self.error();
+                return;
+            }
+            self.start();
+        }
+    }
+
+    fn error(&mut self) {
+        let i2c = regs();
+        // The error flags are cleared by writing zero, the other bits are read only.
+        i2c.sr1().write(|w| unsafe { w.bits(0) });
+        i2c.cr1().modify(|_, w| w.stop().set_bit());
+        self.dma.stop();
+        // The frame stays queued, the blocking driver sends it again.
+        self.queue.abort();
+        FAILED.store(true, Ordering::Relaxed);
+    }
+
+    /// Send all queued frames with the blocking driver, returns the first error.
+    fn drain(&mut self) -> Result<(), DisplayError> {
+        self.queue.drain(&mut self.i2c)
+    }
+}
+
+#[interrupt]
+fn I2C2_EV() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(t) = GLOBAL_TRANSFERS.borrow(cs).borrow_mut().as_mut() {
+            t.event();
+        }
+    });
+}
+
+#[interrupt]
+fn I2C2_ER() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(t) = GLOBAL_TRANSFERS.borrow(cs).borrow_mut().as_mut() {
+            t.error();
+        }
+    });
+}
+
+/// Take the configured I2C2 driver and the DMA channel of its transmitter, returns the interface
+/// for the display.
+pub fn setup(i2c: BlockingI2c<I2C2>, mut dma: C4) -> DmaInterface {
+    let regs = regs();
+    dma.set_peripheral_address(regs.dr().as_ptr() as u32, false);
+    // Memory to peripheral.
+    dma.ch().cr().modify(|_, w| w.dir().set_bit());
+    regs.cr2()
+        .modify(|_, w| w.dmaen().set_bit().itevten().set_bit().iterren().set_bit());
+
+    cortex_m::interrupt::free(|cs| {
+        GLOBAL_TRANSFERS.borrow(cs).borrow_mut().replace(Transfers {
+            queue: Queue::new(),
+            i2c,
+            dma,
+        });
+    });
+
+    unsafe {
+        NVIC::unmask(Interrupt::I2C2_EV);
+        NVIC::unmask(Interrupt::I2C2_ER);
+    }
+    CONFIGURED.store(true, Ordering::Relaxed);
+    DmaInterface { fallback: None }
+}
+
+/// Take over the bus for the panic handler, none if it was never configured. A transfer in
+/// progress is aborted, the DMA and interrupts are no longer used.
+pub fn panic_interface() -> Option<PanicInterface> {
+    if !CONFIGURED.load(Ordering::Relaxed) {
+        return None;
+    }
+    NVIC::mask(Interrupt::I2C2_EV);
+    NVIC::mask(Interrupt::I2C2_ER);
+    let regs = regs();
+    regs.cr2().modify(|_, w| {
+        w.dmaen()
+            .clear_bit()
+            .itevten()
+            .clear_bit()
+            .iterren()
+            .clear_bit()
+    });
+    regs.cr1().modify(|_, w| w.stop().set_bit());
+    poll(|| regs.cr1().read().stop().bit_is_clear()).ok()?;
+    Some(PanicInterface { _private: () })
+}
+
+/// Poll until `done`, or until it is clear that it won't be.
+fn poll(done: impl Fn() -> bool) -> Result<(), DisplayError> {
+    poll_at_most(PANIC_TIMEOUT_POLLS, done)
+}
+
+/// Poll until `done`, at most `polls` times.
+fn poll_at_most(polls: u32, done: impl Fn() -> bool) -> Result<(), DisplayError> {
+    for _ in 0..polls {
+        if done() {
+            return Ok(());
+        }
+    }
+    Err(DisplayError::BusWriteError)
+}
+
+/// Display interface for the panic handler, writes by polling the registers.
+pub struct PanicInterface {
+    _private: (),
+}
+
+impl PanicInterface {
+    /// Send `control` followed by `bytes` as a single transfer.
+    fn transfer(&mut self, control: u8, bytes: &[u8]) -> Result<(), DisplayError> {
+        let i2c = regs();
+        i2c.cr1().modify(|_, w| w.start().set_bit());
+        poll(|| i2c.sr1().read().sb().bit_is_set())?;
+        i2c.dr()
+            .write(|w| unsafe { w.bits(u32::from(DISPLAY_ADDR) << 1) });
+        poll(|| {
+            let sr1 = i2c.sr1().read();
+            sr1.addr().bit_is_set() || sr1.af().bit_is_set()
+        })?;
+        let result = if i2c.sr1().read().af().bit_is_set() {
+            // Not acknowledged, there is no display.
+            i2c.sr1().write(|w| unsafe { w.bits(0) });
+            Err(DisplayError::BusWriteError)
+        } else {
+            let _ = i2c.sr2().read();
+            core::iter::once(&control)
+                .chain(bytes)
+                .try_for_each(|b| {
+                    poll(|| i2c.sr1().read().tx_e().bit_is_set())?;
+                    i2c.dr().write(|w| unsafe { w.bits(u32::from(*b)) });
+                    Ok(())
+                })
+                .and_then(|_| poll(|| i2c.sr1().read().btf().bit_is_set()))
+        };
+        i2c.cr1().modify(|_, w| w.stop().set_bit());
+        poll(|| i2c.cr1().read().stop().bit_is_clear())?;
+        result
+    }
+}
+
+impl WriteOnlyDataCommand for PanicInterface {
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result<(), DisplayError> {
+        match cmd {
+            // A zero control byte makes all bytes that follow commands.
+            DataFormat::U8(v) => self.transfer(0x00, v),
+            _ => Err(DisplayError::DataFormatNotImplemented),
+        }
+    }
+
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result<(), DisplayError> {
+        match buf {
+            DataFormat::U8(v) => self.transfer(CONTROL_DATA, v),
+            _ => Err(DisplayError::DataFormatNotImplemented),
+        }
+    }
+}
+
+/// Whether nothing is queued, such that a flush doesn't have to wait for room. Also true once the
+/// DMA path failed, or a transfer stalled without an error, then the next flush falls back.
+pub fn is_idle() -> bool {
+    cortex_m::interrupt::free(|cs| {
+        let transfers = GLOBAL_TRANSFERS.borrow(cs).borrow();
+        let Some(t) = transfers.as_ref() else {
+            return true;
+        };
+        if t.queue.stalled(crate::clock::millis()) {
+            FAILED.store(true, Ordering::Relaxed);
+        }
+        t.queue.is_idle(failed())
+    })
+}
+
+/// Whether the DMA path failed and the blocking driver is used instead.
+pub fn failed() -> bool {
+    FAILED.load(Ordering::Relaxed)
+}
+
+/// Display interface that queues the writes, see the module documentation.
+pub struct DmaInterface {
+    /// The transfers, taken out of the interrupts' reach once the DMA path failed.
+    fallback: Option<Transfers>,
+}
+
+impl DmaInterface {
+    fn send(&mut self, mut bytes: &[u8], push: PushFn) -> Result<(), DisplayError> {
+        let start = crate::clock::millis();
+        while !bytes.is_empty() {
+            if let Some(t) = self.fallback.as_mut() {
+                while !bytes.is_empty() {
+                    match t.queue.push(bytes, push) {
+                        0 => t.drain()?,
+                        taken => bytes = &bytes[taken..],
+                    }
+                }
+                return t.drain();
+            }
+
+            let taken = cortex_m::interrupt::free(|cs| {
+                let mut transfers = GLOBAL_TRANSFERS.borrow(cs).borrow_mut();
+                let t = transfers.as_mut()?;
+                if failed() {
+                    return None;
+                }
+                let taken = t.queue.push(bytes, push);
+                t.start();
+                Some(taken)
+            });
+            match taken {
+                Some(0) if crate::clock::millis().wrapping_sub(start) < TIMEOUT_MS => {
+                    // The queue is full, the interrupt makes room as frames are sent.
+                }
+                Some(0) | None => self.fall_back()?,
+                Some(taken) => bytes = &bytes[taken..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop using the DMA, the frames still queued are sent with the blocking driver.
+    fn fall_back(&mut self) -> Result<(), DisplayError> {
+        NVIC::mask(Interrupt::I2C2_EV);
+        NVIC::mask(Interrupt::I2C2_ER);
+        FAILED.store(true, Ordering::Relaxed);
+        let mut t = cortex_m::interrupt::free(|cs| GLOBAL_TRANSFERS.borrow(cs).borrow_mut().take())
+            .ok_or(DisplayError::BusWriteError)?;
+        let regs = regs();
+        if t.queue.active {
+            // Stuck in the middle of a transfer, abort it.
+            regs.cr1().modify(|_, w| w.stop().set_bit());
+            t.queue.abort();
+        }
+        t.dma.stop();
+        regs.cr2().modify(|_, w| {
+            w.dmaen()
+                .clear_bit()
+                .itevten()
+                .clear_bit()
+                .iterren()
+                .clear_bit()
+        });
+        let result = t.drain();
+        self.fallback = Some(t);
+        result
+    }
+}
+
+impl WriteOnlyDataCommand for DmaInterface {
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result<(), DisplayError> {
+        match cmd {
+            DataFormat::U8(v) => self.send(v, Frame::push_commands),
+            _ => Err(DisplayError::DataFormatNotImplemented),
+        }
+    }
+
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result<(), DisplayError> {
+        match buf {
+            DataFormat::U8(v) => self.send(v, Frame::push_data),
+            _ => Err(DisplayError::DataFormatNotImplemented),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    /// Keeps the writes to the bus, as the address and the bytes.
+    #[derive(Default)]
+    struct RecordingBus {
+        writes: Vec<(u8, Vec<u8>)>,
+    }
+
+    impl ErrorType for RecordingBus {
+        type Error = core::convert::Infallible;
+    }
+
+    impl I2c for RecordingBus {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            for op in operations {
+                if let Operation::Write(bytes) = op {
+                    self.writes.push((address, bytes.to_vec()));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_fall_back() {
+        let mut q = Queue::new();
+        assert_eq!(q.push(&[0xb0], Frame::push_commands), 1);
+        assert_eq!(q.push(&[1, 2], Frame::push_data), 2);
+        q.begin(1000);
+        assert!(!q.is_idle(false));
+
+        // An error leaves the frame queued, the next update is let through and reaches the panel
+        // after it, through the blocking driver.
+        q.abort();
+        assert!(q.is_idle(true));
+        assert_eq!(q.push(&[3], Frame::push_data), 1);
+        let mut bus = RecordingBus::default();
+        assert!(q.drain(&mut bus).is_ok());
+        assert_eq!(
+            bus.writes,
+            [
+                (DISPLAY_ADDR, vec![0x80, 0xb0, 0x40, 1, 2]),
+                (DISPLAY_ADDR, vec![0x40, 3])
+            ]
+        );
+        assert!(q.is_empty());
+
+        // A transfer that doesn't complete, without an error, is caught by its age.
+        assert_eq!(q.push(&[4], Frame::push_data), 1);
+        q.begin(u32::MAX - 10);
+        assert!(!q.stalled(TIMEOUT_MS - 11));
+        assert!(q.stalled(TIMEOUT_MS - 10));
+        q.pop();
+        assert!(!q.stalled(TIMEOUT_MS));
+    }
+
+    #[test]
+    fn test_queue() {
+        let mut q = Queue::new();
+        // Position commands and the data following them form a single transfer.
+        assert_eq!(q.push(&[0xb3, 0x06], Frame::push_commands), 2);
+        assert_eq!(q.push(&[0x11], Frame::push_commands), 1);
+        assert_eq!(q.push(&[1, 2, 3], Frame::push_data), 3);
+        assert_eq!(q.count, 1);
+        assert_eq!(
+            q.front().unwrap().bytes(),
+            [0x80, 0xb3, 0x80, 0x06, 0x80, 0x11, 0x40, 1, 2, 3]
+        );
+
+        // Nothing is appended after data, nor to the frame being sent.
+        assert_eq!(q.push(&[0xaf], Frame::push_commands), 1);
+        assert_eq!(q.count, 2);
+        q.pop();
+        q.active = true;
+        assert_eq!(q.push(&[4], Frame::push_data), 1);
+        assert_eq!(q.count, 2);
+        assert_eq!(q.frames[q.first + 1].bytes(), [0x40, 4]);
+        q.pop();
+        q.pop();
+        assert!(q.is_empty());
+
+        // Long writes are split over frames, until the queue is full.
+        let data = [0xaa; 4 * FRAME_CAPACITY];
+        let mut taken = 0;
+        while let n @ 1.. = q.push(&data[taken..], Frame::push_data) {
+            taken += n;
+        }
+        assert_eq!(q.count, QUEUE_LEN);
+        assert_eq!(taken, QUEUE_LEN * (FRAME_CAPACITY - 1));
+        assert_eq!(q.push(&[0xae], Frame::push_commands), 0);
+        q.pop();
+        assert_eq!(q.push(&[0xae], Frame::push_commands), 1);
+    }
+}
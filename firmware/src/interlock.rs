@@ -0,0 +1,241 @@
+#![allow(unsafe_code)]
+//! Over-temperature interlock, independent of the oven control.
+//!
+//! The interlock output is asserted while the temperature is known to be below the limit. It
+//! de-asserts when the temperature exceeds the limit, the thermocouple is open or shorted, reading
+//! the sensor fails or no reading arrived for a while. It then stays de-asserted until it is reset
+//! by hand, even if the condition cleared. The latch is kept in RAM that isn't initialised at boot,
+//! such that it also holds across a reset by the watchdog or after a panic, only a power cycle
+//! clears it.
+use core::mem::MaybeUninit;
+
+use crate::clock;
+
+/// Default limit in Celsius, above the cutoff of the oven control.
+pub const DEFAULT_LIMIT: f32 = 270.0;
+/// Default age of the last reading after which the interlock trips, see `clock::is_stale`.
+pub const DEFAULT_MAX_AGE: u32 = 1000;
+
+/// Marks an intact record, "ilck".
+const MAGIC: u32 = 0x696c_636b;
+
+/// Reasons the interlock tripped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Trip {
+    /// The temperature exceeded the limit.
+    OverTemperature,
+    /// The sensor reports the thermocouple open or shorted.
+    Thermocouple,
+    /// Communication with the sensor failed.
+    Bus,
+    /// No reading arrived in time.
+    Stale,
+}
+
+impl Trip {
+    const ALL: [Trip; 4] = [
+        Trip::OverTemperature,
+        Trip::Thermocouple,
+        Trip::Bus,
+        Trip::Stale,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Trip::OverTemperature => "over temperature",
+            Trip::Thermocouple => "thermocouple fault",
+            Trip::Bus => "i2c failure",
+            Trip::Stale => "stale reading",
+        }
+    }
+}
+
+/// The latched trip, with what it takes to tell it apart from the random contents of RAM after
+/// power up.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Record {
+    magic: u32,
+    /// Index in `Trip::ALL` plus one, zero while not latched.
+    trip: u32,
+    /// The complement of `trip`.
+    check: u32,
+}
+
+impl Record {
+    /// An intact record of `latched`.
+    pub const fn new(latched: Option<Trip>) -> Self {
+        let trip = match latched {
+            None => 0,
+            Some(trip) => trip as u32 + 1,
+        };
+        Self {
+            magic: MAGIC,
+            trip,
+            check: !trip,
+        }
+    }
+
+    /// The latched trip, none unless the record is intact and holds one.
+    pub fn latched(&self) -> Option<Trip> {
+        if self.magic != MAGIC || self.check != !self.trip {
+            return None;
+        }
+        Trip::ALL.get((self.trip as usize).checked_sub(1)?).copied()
+    }
+}
+
+#[unsafe(link_section = ".uninit.interlock")]
+static mut RECORD: MaybeUninit<Record> = MaybeUninit::uninit();
+
+/// The trip the interlock was latched for before the last reset, none after a power cycle.
+pub fn restore() -> Option<Trip> {
+    unsafe { (&raw const RECORD).cast::<Record>().read_volatile() }.latched()
+}
+
+fn store(latched: Option<Trip>) {
+    unsafe {
+        (&raw mut RECORD)
+            .cast::<Record>()
+            .write_volatile(Record::new(latched))
+    };
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Interlock {
+    /// Limit in Celsius.
+    pub limit: f32,
+    /// Age of the last reading after which the interlock trips.
+    pub max_age: u32,
+    /// Time of the last reading below the limit, none until there is one.
+    last: Option<u32>,
+    /// Time of the start or last reset, to detect readings that never arrive.
+    since: u32,
+    latched: Option<Trip>,
+}
+
+impl Interlock {
+    pub fn new(limit: f32, now: u32) -> Self {
+        Self {
+            limit,
+            max_age: DEFAULT_MAX_AGE,
+            last: None,
+            since: now,
+            latched: None,
+        }
+    }
+
+    /// Whether the output is asserted.
+    pub fn is_ok(&self) -> bool {
+        self.latched.is_none() && self.last.is_some()
+    }
+
+    /// The reason the interlock is latched.
+    pub fn latched(&self) -> Option<Trip> {
+        self.latched
+    }
+
+    /// Latch for `trip`, returns it unless already latched.
+    pub fn trip(&mut self, trip: Trip) -> Option<Trip> {
+        if self.latched.is_some() {
+            return None;
+        }
+        self.latched = Some(trip);
+        store(self.latched);
+        Some(trip)
+    }
+
+    /// A reading at `now`, returns the trip if this latches the interlock.
+    pub fn reading(&mut self, now: u32, temperature: f32) -> Option<Trip> {
+        if temperature > self.limit {
+            return self.trip(Trip::OverTemperature);
+        }
+        self.last = Some(now);
+        None
+    }
+
+    /// Check the age of the last reading at `now`, returns the trip if this latches the interlock.
+    pub fn service(&mut self, now: u32) -> Option<Trip> {
+        let last = self.last.unwrap_or(self.since);
+        if clock::is_stale(now, last, self.max_age) {
+            return self.trip(Trip::Stale);
+        }
+        None
+    }
+
+    /// Reset by hand at `now`, the output is asserted again from the next reading below the limit.
+    pub fn reset(&mut self, now: u32) {
+        self.latched = None;
+        store(self.latched);
+        self.last = None;
+        self.since = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interlock() {
+        let mut interlock = Interlock::new(250.0, 0);
+        // Not asserted until there is a reading.
+        assert!(!interlock.is_ok());
+        assert_eq!(interlock.reading(50, 25.0), None);
+        assert!(interlock.is_ok());
+        assert_eq!(interlock.service(1050), None);
+        assert_eq!(interlock.service(40), None);
+
+        // Over the limit, and it stays latched once the temperature drops.
+        assert_eq!(interlock.reading(1100, 250.5), Some(Trip::OverTemperature));
+        assert!(!interlock.is_ok());
+        assert_eq!(interlock.reading(1150, 25.0), None);
+        assert_eq!(interlock.trip(Trip::Bus), None);
+        assert!(!interlock.is_ok());
+        assert_eq!(interlock.latched(), Some(Trip::OverTemperature));
+
+        interlock.reset(2000);
+        assert_eq!(interlock.latched(), None);
+        assert!(!interlock.is_ok());
+        assert_eq!(interlock.reading(2050, 25.0), None);
+        assert!(interlock.is_ok());
+
+        // No readings.
+        assert_eq!(interlock.service(3050), None);
+        assert_eq!(interlock.service(3051), Some(Trip::Stale));
+        assert_eq!(interlock.service(3052), None);
+        assert!(!interlock.is_ok());
+
+        // Readings that never arrive after a reset.
+        interlock.reset(4000);
+        assert_eq!(interlock.service(5001), Some(Trip::Stale));
+
+        interlock.reset(6000);
+        assert_eq!(interlock.trip(Trip::Thermocouple), Some(Trip::Thermocouple));
+        assert_eq!(interlock.reading(6050, 25.0), None);
+        assert!(!interlock.is_ok());
+        assert_eq!(restore(), Some(Trip::Thermocouple));
+    }
+
+    #[test]
+    fn test_record() {
+        for trip in Trip::ALL {
+            assert_eq!(Record::new(Some(trip)).latched(), Some(trip));
+        }
+        assert_eq!(Record::new(None).latched(), None);
+
+        // Anything but an intact record isn't latched.
+        let mut damaged = Record::new(Some(Trip::Bus));
+        damaged.trip = 1;
+        assert_eq!(damaged.latched(), None);
+        let mut damaged = Record::new(Some(Trip::Bus));
+        damaged.magic = 0;
+        assert_eq!(damaged.latched(), None);
+        let damaged = Record {
+            magic: MAGIC,
+            trip: 9,
+            check: !9,
+        };
+        assert_eq!(damaged.latched(), None);
+    }
+}
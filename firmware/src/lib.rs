@@ -12,31 +12,71 @@
 #![cfg_attr(not(test), no_std)]
 #![cfg_attr(not(test), no_main)]
 
-#[cfg(not(target_os = "linux"))]
-use panic_halt as _;
-
 use cortex_m_semihosting::hprintln;
 
 use cortex_m::asm::delay;
 
 use stm32f1xx_hal::{pac, prelude::*, rcc, timer::Timer};
 
-use stm32f1xx_hal::usb::{Peripheral, UsbBus};
+use stm32f1xx_hal::usb::Peripheral;
 
 use usb_device::prelude::*;
-use usbd_serial::{SerialPort, USB_CLASS_CDC};
 
+use stm32f1xx_hal::gpio::{Edge, ExtiPin};
 use stm32f1xx_hal::i2c::{BlockingI2c, DutyCycle, Mode};
+use stm32f1xx_hal::spi;
+use stm32f1xx_hal::watchdog::IndependentWatchdog;
 
-use ssd1306::{I2CDisplayInterface, Ssd1306, prelude::*};
+use ssd1306::{Ssd1306, prelude::*};
 
+pub mod button;
 pub mod clock;
+pub mod command;
+pub mod control;
+pub mod controller;
 pub mod display;
 pub mod display_buffer;
+pub mod graph;
+pub mod history;
+pub mod i2c_dma;
+pub mod interlock;
 pub mod mcp9600;
+pub mod panic;
+pub mod profile;
+pub mod reset;
+pub mod ring;
+pub mod runlog;
+pub mod scheduler;
+pub mod screensaver;
+pub mod seven_segment;
+pub mod spi_flash;
+pub mod statistics;
+pub mod storage;
+pub mod usb;
 pub mod util;
+pub mod widget;
 use clock::ElapsedMillis;
 
+/// Capacity of the rate of change buffer. There is at most one sample per 50 ms sensor task, so
+/// this covers windows up to at least 12.75 seconds.
+const AVERAGE_CAPACITY: usize = 256;
+
+/// Default age in milliseconds after which a reading is stale, conversions take at most 320 ms.
+const STALE_AGE: u32 = 1000;
+
+/// Timeout of the independent watchdog in milliseconds, a pass of the main loop takes far less.
+const WATCHDOG_MS: u32 = 1000;
+
+/// Characters of a panic message per diagnostic line, such that the line fits a `StackString`.
+const PANIC_COLUMNS: usize = 48;
+
+/// Long running operations on the run log, performed a bit at a time from the main loop.
+enum StorageTask {
+    Runs(runlog::RunScan),
+    Download(runlog::SampleScan),
+    Erase,
+}
+
 pub fn main() -> ! {
     // ------------------------------------------------------
     // Oscillators & peripheral setup.
@@ -51,6 +91,8 @@ pub fn main() -> ! {
     // Take ownership over the raw flash and rcc devices and convert them into the corresponding
     // HAL structs
     let mut flash = dp.FLASH.constrain();
+    // Before the flags are lost, reported once the serial port is up.
+    let reset_cause = reset::take(&dp.RCC);
     let rcc = dp.RCC.constrain();
 
     // Freeze the configuration of all the clocks in the system and store the frozen frequencies in
@@ -77,9 +119,8 @@ pub fn main() -> ! {
     //let mut timer = Timer::syst(cp.SYST, &rcc.clocks).counter_us();
     //timer.start(stm32f1xx_hal::time::us(100)).unwrap();
 
-    // Setup our milliseconds clock & measurement interval timer.
+    // Setup our milliseconds clock, this also wakes the scheduler every millisecond.
     clock::setup_ms_clock(dp.TIM2, &mut rcc);
-    let mut elapsed = ElapsedMillis::new();
 
     // ------------------------------------------------------
     //  Setup USB & CDC
@@ -93,32 +134,101 @@ pub fn main() -> ! {
     usb_dp.set_low();
     delay(rcc.clocks.sysclk().raw() / 100);
 
-    // Setup USB and CDC serial port.
+    // Setup USB and CDC serial port, these are serviced from the USB interrupt.
     let usb = Peripheral {
         usb: dp.USB,
         pin_dm: gpioa.pa11,
         pin_dp: usb_dp.into_floating_input(&mut gpioa.crh),
     };
-    let usb_bus = UsbBus::new(usb);
-
-    let mut serial = SerialPort::new(&usb_bus);
+    let mut serial = usb::setup(usb);
+    sprintln!(serial, "# reset cause {}", reset_cause);
+    if reset_cause.is_watchdog() {
+        sprintln!(serial, "# watchdog reset");
+    }
+    // Held in the transmit buffer until a host reads it, like the rest of the boot messages.
+    if let Some(message) = panic::take().message() {
+        for line in panic::lines(message, PANIC_COLUMNS) {
+            sprintln!(serial, "# panic {}", line);
+        }
+    }
 
-    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
-        .device_class(USB_CLASS_CDC)
-        .strings(&[StringDescriptors::default()
-            .manufacturer("Fake Company")
-            .product("Serial port")
-            .serial_number("TEST")])
-        .unwrap()
-        .build();
+    // ------------------------------------------------------
+    // Setup SPI1 for the optional NOR flash, used to log runs without a host.
+    let sck = gpioa.pa5;
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7;
+    let mut flash_cs = gpioa.pa4.into_push_pull_output(&mut gpioa.crl);
+    flash_cs.set_high();
+    let spi1 = dp.SPI1.spi(
+        (Some(sck), Some(miso), Some(mosi)),
+        spi::Mode {
+            polarity: spi::Polarity::IdleLow,
+            phase: spi::Phase::CaptureOnFirstTransition,
+        },
+        8.MHz(),
+        &mut rcc,
+    );
+    let mut nor = spi_flash::SpiNorFlash::new(spi1, flash_cs);
+    let mut run_log = match nor.probe() {
+        Ok(true) => runlog::RunLog::new(nor).ok(),
+        _ => None,
+    };
+    if let Some(log) = run_log.as_mut() {
+        sprintln!(
+            serial,
+            "# storage {} of {} records used",
+            log.len(),
+            log.capacity()
+        );
+        if let Err(e) = log.start_run(clock::millis()) {
+            sprintln!(serial, "# storage run failed {:?}", e);
+        }
+    } else {
+        sprintln!(serial, "# no storage found");
+    }
+    let mut storage_elapsed = ElapsedMillis::new();
+    let mut storage_task: Option<StorageTask> = None;
 
     // ------------------------------------------------------
     // Setup i2c for temperature sensor
     led.set_high();
 
     let mut afio = dp.AFIO.constrain(&mut rcc);
+
+    // ------------------------------------------------------
+    // Push button on PA0 to ground, cycles the display pages.
+    let mut exti = dp.EXTI;
+    let mut button_pin = gpioa.pa0.into_pull_up_input(&mut gpioa.crl);
+    button_pin.make_interrupt_source(&mut afio);
+    button_pin.trigger_on_edge(&mut exti, Edge::RisingFalling);
+    button_pin.enable_interrupt(&mut exti);
+    button::setup(button_pin);
+    let mut debouncer = button::Debouncer::default();
+
     // Acquire the GPIOB peripheral
-    let gpiob = dp.GPIOB.split(&mut rcc);
+    let mut gpiob = dp.GPIOB.split(&mut rcc);
+
+    // ------------------------------------------------------
+    // Solid state relay of the oven on PB0, on while high. Only driven by the controller while a
+    // reflow run is in progress and control is switched on.
+    let mut ssr = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+    ssr.set_low();
+    let mut control = control::Control::new(control::DEFAULT_GAINS);
+    let mut control_enabled = false;
+
+    // Over-temperature interlock on PB1, high while the temperature is known to be below the limit.
+    // Independent of the control, for example to hold the contactor of the oven.
+    let mut interlock_pin = gpiob.pb1.into_push_pull_output(&mut gpiob.crl);
+    interlock_pin.set_low();
+    let mut interlock = interlock::Interlock::new(interlock::DEFAULT_LIMIT, clock::millis());
+    // A latch from before a reset holds, only a power cycle or a reset by hand clears it.
+    if let Some(trip) = interlock::restore() {
+        interlock.trip(trip);
+        sprintln!(serial, "# interlock latched {} before reset", trip.name());
+    }
+
+    // Readings older than this are flagged as stale.
+    let mut stale_age = STALE_AGE;
 
     let scl = gpiob.pb8;
     let sda = gpiob.pb9;
@@ -164,8 +274,10 @@ pub fn main() -> ! {
             1000,
             1000,
         );
-    let interface = I2CDisplayInterface::new(i2c2);
-    let mut disp = display::Display::new(interface);
+    // Display writes are sent by DMA, such that the main loop doesn't wait for the bus.
+    let dma1 = dp.DMA1.split(&mut rcc);
+    let interface = i2c_dma::setup(i2c2, dma1.4);
+    let mut disp = display::Display::new(controller::panel(interface));
     if !disp.init() {
         sprintln!(serial, "# disp init failed.");
     }
@@ -174,28 +286,151 @@ pub fn main() -> ! {
 
     let mut contents: display::Contents = display::Contents::default();
 
-    let mut average: display::Average = Default::default();
+    // Windows over which the rates of change are determined, in milliseconds.
+    let mut short_window: u32 = 2000;
+    let mut long_window: u32 = 9000;
+    let mut estimator = display::RateEstimator::default();
+    let mut average: display::Average<AVERAGE_CAPACITY> = Default::default();
+
+    // Samples retained on the device, ~25 minutes at full resolution before it starts decimating.
+    let mut history: history::History<1536> = history::History::new(1000);
+    let mut history_dump: Option<history::HistoryCursor> = None;
+
+    let mut lines = command::LineBuffer::default();
+
+    // ------------------------------------------------------
+    // Scheduler, the core sleeps between the tasks.
+    let now = clock::millis();
+    let mut sensor_task = scheduler::Periodic::new(now, 50);
+    let mut display_task = scheduler::Periodic::new(now, 10);
+    let mut button_task = scheduler::Periodic::new(now, 5);
+    let mut jitter_task = scheduler::Periodic::new(now, 10_000);
+    let mut reported_dropped = 0;
+    let mut reported_dma_failure = false;
+    let mut reflow_phase: Option<profile::Phase> = None;
+
+    // ------------------------------------------------------
+    // Independent watchdog, only fed when the main loop goes around. Started last, as probing the
+    // storage and the display can take a while.
+    let mut watchdog = IndependentWatchdog::new(dp.IWDG);
+    watchdog.stop_on_debug(&dp.DBGMCU, true);
+    watchdog.start(stm32f1xx_hal::time::ms(WATCHDOG_MS));
 
     loop {
-        if elapsed >= stm32f1xx_hal::time::ms(50) {
-            //sprintln!(serial, "{:?}, {}", elapsed, clock::millis());
+        let now = clock::millis();
+
+        if sensor_task.due(now) {
+            //sprintln!(serial, "{:?}, {}", sensor_task, clock::millis());
             let s = mcp.read_status();
             if let Ok(v) = s {
                 //sprintln!(serial, "{}, {:?}", clock::millis(), v.conversion_complete);
+                // A newly raised alert wakes the display.
+                // Without a thermocouple, or with a shorted one, the reading is meaningless.
+                if v.out_of_range || v.short_circuit {
+                    if let Some(cutoff) = control.update(now, None, None) {
+                        sprintln!(serial, "# control cutoff {}", cutoff.name());
+                    }
+                    if let Some(trip) = interlock.trip(interlock::Trip::Thermocouple) {
+                        sprintln!(serial, "# interlock latched {}", trip.name());
+                    }
+                }
+                if v.alerts
+                    .iter()
+                    .zip(contents.alerts)
+                    .any(|(new, old)| *new && !old)
+                    && let Err(e) = disp.wake(now)
+                {
+                    sprintln!(serial, "# disp wake: {:?}", e);
+                }
+                contents.alerts = v.alerts;
                 if v.conversion_complete {
                     if let Ok(hot) = mcp.read_hot_junction() {
                         let v = hot.as_f32();
+                        if matches!(contents.status, display::InternalStatus::Stale) {
+                            sprintln!(serial, "# reading resumed");
+                        }
                         // And update the conents in the display.
                         contents.temperature = v;
                         contents.time = clock::millis();
                         contents.status = display::InternalStatus::Good;
+                        contents.stats.add(contents.time, v);
+                        if let Ok(cold) = mcp.read_cold_junction() {
+                            contents.cold_junction = cold.as_f32();
+                        }
 
                         // Update the averaging buffer.
                         average.add_measurement(contents.time, contents.temperature);
-                        contents.avg_short = average.get_average(2000);
-                        contents.avg_long = average.get_average(9000);
+                        contents.avg_short = average.get_change(short_window, estimator);
+                        contents.avg_long = average.get_change(long_window, estimator);
+
+                        history.add(contents.time, contents.temperature);
+                        contents.graph.add(contents.time, contents.temperature);
+
+                        if let Some(trip) = interlock.reading(contents.time, v) {
+                            sprintln!(
+                                serial,
+                                "# interlock latched {} {:.1}",
+                                trip.name(),
+                                contents.unit.convert(v)
+                            );
+                        }
+                        let target = contents.run.and_then(|r| r.target(contents.time));
+                        if let Some(cutoff) = control.update(contents.time, Some(v), target) {
+                            sprintln!(serial, "# control cutoff {}", cutoff.name());
+                        }
 
-                        sprintln!(serial, "{}, {:.4}", clock::millis(), v,);
+                        // Store at most one sample per second, this lasts days on a 2 MiB flash.
+                        if let Some(log) = run_log.as_mut()
+                            && log.is_running()
+                            && storage_elapsed >= stm32f1xx_hal::time::ms(1000)
+                        {
+                            storage_elapsed.reset();
+                            if let Err(e) = log.append_sample(contents.time, contents.temperature) {
+                                sprintln!(serial, "# storage append failed {:?}", e);
+                            }
+                        }
+
+                        // Live output pauses while a dump is in progress.
+                        let dumping = history_dump.is_some()
+                            || matches!(
+                                storage_task,
+                                Some(StorageTask::Runs(_) | StorageTask::Download(_))
+                            );
+                        if !dumping {
+                            // While controlling the oven the setpoint and output follow.
+                            if control.is_active()
+                                && let Some(setpoint) = control.setpoint()
+                            {
+                                sprintln!(
+                                    serial,
+                                    "{}, {:.4}, {:.1}, {:.2}",
+                                    clock::millis(),
+                                    contents.unit.convert(v),
+                                    contents.unit.convert(setpoint),
+                                    control.duty()
+                                );
+                            } else {
+                                sprintln!(
+                                    serial,
+                                    "{}, {:.4}",
+                                    clock::millis(),
+                                    contents.unit.convert(v)
+                                );
+                            }
+                        }
+
+                        // Announce the phases of a reflow run as they are entered.
+                        let phase = contents.run.map(|r| r.phase(contents.time));
+                        if phase != reflow_phase {
+                            if let Some(phase) = phase {
+                                sprintln!(serial, "# reflow {}", phase.name());
+                            }
+                            if phase == Some(profile::Phase::Done) && control.is_active() {
+                                control.stop();
+                                sprintln!(serial, "# control stop");
+                            }
+                            reflow_phase = phase;
+                        }
 
                         if let Err(e) = disp.update(&contents) {
                             sprintln!(serial, "# disp update: {:?}", e);
@@ -204,6 +439,12 @@ pub fn main() -> ! {
                     } else {
                         let _ = mcp.clear_status();
                         contents.status = display::InternalStatus::Error;
+                        if let Some(cutoff) = control.update(now, None, None) {
+                            sprintln!(serial, "# control cutoff {}", cutoff.name());
+                        }
+                        if let Some(trip) = interlock.trip(interlock::Trip::Bus) {
+                            sprintln!(serial, "# interlock latched {}", trip.name());
+                        }
                         if let Err(e) = disp.update(&contents) {
                             sprintln!(serial, "# disp update: {:?}", e);
                         }
@@ -212,22 +453,424 @@ pub fn main() -> ! {
             } else {
                 sprintln!(serial, "# status failed {}, {:?}", clock::millis(), s);
                 contents.status = display::InternalStatus::Error;
+                if let Some(cutoff) = control.update(now, None, None) {
+                    sprintln!(serial, "# control cutoff {}", cutoff.name());
+                }
+                if let Some(trip) = interlock.trip(interlock::Trip::Bus) {
+                    sprintln!(serial, "# interlock latched {}", trip.name());
+                }
+                if let Err(e) = disp.update(&contents) {
+                    sprintln!(serial, "# disp update: {:?}", e);
+                }
+            }
+
+            // Conversions that never complete leave the last reading on the display, flag it.
+            let age = now.wrapping_sub(contents.time);
+            if matches!(contents.status, display::InternalStatus::Good)
+                && clock::is_stale(now, contents.time, stale_age)
+            {
+                contents.status = display::InternalStatus::Stale;
+                sprintln!(serial, "# reading stale {} ms", age);
                 if let Err(e) = disp.update(&contents) {
                     sprintln!(serial, "# disp update: {:?}", e);
                 }
             }
-            elapsed.reset();
             led.toggle();
-            //print_update = true;
         }
 
-        if let Err(e) = disp.update_partial() {
-            sprintln!(serial, "# update failed: {:?}", e);
+        while let Some(byte) = serial.read_byte() {
+            let Some(line) = lines.push(byte) else {
+                continue;
+            };
+            match command::Command::parse(line) {
+                Some(command::Command::History) => {
+                    sprintln!(
+                        serial,
+                        "# history {} samples interval {} ms",
+                        history.len(),
+                        history.interval()
+                    );
+                    sprintln!(serial, "# unit {}", contents.unit.suffix());
+                    history_dump = Some(history.cursor());
+                }
+                Some(command::Command::HistoryClear) => {
+                    history.clear();
+                    sprintln!(serial, "# history cleared");
+                }
+                Some(command::Command::Runs) => {
+                    storage_task = Some(StorageTask::Runs(Default::default()));
+                }
+                Some(command::Command::RunsErase) => {
+                    sprintln!(serial, "# storage erasing");
+                    storage_task = Some(StorageTask::Erase);
+                }
+                Some(command::Command::Download(run)) => {
+                    sprintln!(serial, "# download run {}", run);
+                    sprintln!(serial, "# unit {}", contents.unit.suffix());
+                    storage_task = Some(StorageTask::Download(runlog::SampleScan::new(run)));
+                }
+                Some(command::Command::Page(page)) => {
+                    if let Err(e) = disp.set_page(page) {
+                        sprintln!(serial, "# disp page: {:?}", e);
+                    }
+                }
+                Some(command::Command::PageNext) => {
+                    if let Err(e) = disp.next_page() {
+                        sprintln!(serial, "# disp page: {:?}", e);
+                    }
+                }
+                Some(command::Command::Layout(layout)) => {
+                    if let Err(e) = disp.set_layout(layout) {
+                        sprintln!(serial, "# disp layout: {:?}", e);
+                    }
+                }
+                Some(command::Command::Decimals(decimals)) => {
+                    contents.decimals = decimals;
+                }
+                Some(command::Command::ReflowStart(profile)) => {
+                    contents.run = Some(profile::Run::new(profile, clock::millis()));
+                    sprintln!(serial, "# reflow start {}", profile.name);
+                    if control_enabled {
+                        control.start(clock::millis());
+                        sprintln!(serial, "# control start");
+                    }
+                    if let Err(e) = disp.set_page(display::Page::Reflow) {
+                        sprintln!(serial, "# disp page: {:?}", e);
+                    }
+                }
+                Some(command::Command::ReflowStop) => {
+                    contents.run = None;
+                    sprintln!(serial, "# reflow stop");
+                    if control.is_active() {
+                        control.stop();
+                        sprintln!(serial, "# control stop");
+                    }
+                }
+                Some(command::Command::ControlOn) => {
+                    control_enabled = true;
+                    sprintln!(serial, "# control on");
+                    // Take over a run that is already in progress.
+                    if contents.run.is_some() && !control.is_active() {
+                        control.start(clock::millis());
+                        sprintln!(serial, "# control start");
+                    }
+                }
+                Some(command::Command::ControlOff) => {
+                    control_enabled = false;
+                    control.stop();
+                    sprintln!(serial, "# control off");
+                }
+                Some(command::Command::ControlGains(gains)) => {
+                    control.gains = gains;
+                    sprintln!(
+                        serial,
+                        "# control pid {} {} {}",
+                        gains.kp,
+                        gains.ki,
+                        gains.kd
+                    );
+                }
+                Some(command::Command::InterlockReset) => {
+                    interlock.reset(clock::millis());
+                    sprintln!(serial, "# interlock reset");
+                }
+                Some(command::Command::InterlockLimit(limit)) => {
+                    interlock.limit = contents.unit.to_celsius(limit);
+                    sprintln!(serial, "# interlock limit {:.1}", limit);
+                }
+                Some(command::Command::SensorStale(age)) => {
+                    // Only the flag, the interlock and the control keep their own limits.
+                    stale_age = age;
+                    sprintln!(serial, "# sensor stale {} ms", age);
+                }
+                Some(command::Command::Stats) => {
+                    let (stats, unit) = (&contents.stats, contents.unit);
+                    sprintln!(
+                        serial,
+                        "# stats since {} ms samples {}",
+                        stats.since(),
+                        stats.count()
+                    );
+                    if let (Some(min), Some(max), Some(mean)) =
+                        (stats.min(), stats.max(), stats.mean())
+                    {
+                        sprintln!(
+                            serial,
+                            "# stats max {:.4} at {} ms",
+                            unit.convert(max.temperature),
+                            max.time
+                        );
+                        sprintln!(
+                            serial,
+                            "# stats min {:.4} at {} ms",
+                            unit.convert(min.temperature),
+                            min.time
+                        );
+                        sprintln!(serial, "# stats mean {:.4}", unit.convert(mean));
+                    }
+                    sprintln!(
+                        serial,
+                        "# stats above {:.1} for {} ms",
+                        unit.convert(stats.threshold()),
+                        stats.time_above()
+                    );
+                }
+                Some(command::Command::StatsReset) => {
+                    contents.stats.reset(clock::millis());
+                    sprintln!(serial, "# stats reset");
+                }
+                Some(command::Command::StatsThreshold(threshold)) => {
+                    contents
+                        .stats
+                        .set_threshold(contents.unit.to_celsius(threshold));
+                    sprintln!(serial, "# stats threshold {:.1}", threshold);
+                }
+                Some(command::Command::RateShort(window)) => {
+                    short_window = window;
+                    sprintln!(serial, "# rate short {} ms", window);
+                }
+                Some(command::Command::RateLong(window)) => {
+                    long_window = window;
+                    sprintln!(serial, "# rate long {} ms", window);
+                }
+                Some(command::Command::RateEstimator(e)) => {
+                    estimator = e;
+                    sprintln!(serial, "# rate estimator {:?}", e);
+                }
+                Some(command::Command::RateUnit(unit)) => {
+                    contents.rate_unit = unit;
+                    sprintln!(serial, "# rate unit {:?}", unit);
+                }
+                Some(command::Command::Unit(unit)) => {
+                    contents.unit = unit;
+                    sprintln!(serial, "# unit {}", unit.suffix());
+                }
+                Some(command::Command::ScreenContrast(contrast)) => {
+                    if let Err(e) = disp.set_contrast(contrast) {
+                        sprintln!(serial, "# disp contrast: {:?}", e);
+                    }
+                }
+                Some(command::Command::ScreenDim(seconds)) => {
+                    disp.screensaver_mut().dim_after = seconds.saturating_mul(1000);
+                    sprintln!(serial, "# screen dim {} s", seconds);
+                }
+                Some(command::Command::ScreenOff(seconds)) => {
+                    disp.screensaver_mut().off_after = seconds.saturating_mul(1000);
+                    sprintln!(serial, "# screen off {} s", seconds);
+                }
+                Some(command::Command::ScreenShift(seconds)) => {
+                    disp.screensaver_mut().shift_period = seconds.saturating_mul(1000);
+                    sprintln!(serial, "# screen shift {} s", seconds);
+                }
+                Some(command::Command::ScreenWake) => {
+                    if let Err(e) = disp.wake(clock::millis()) {
+                        sprintln!(serial, "# disp wake: {:?}", e);
+                    }
+                }
+                None => sprintln!(serial, "# unknown command"),
+            }
         }
 
-        // This returns true if the serial port has data available for reading.
-        if !usb_dev.poll(&mut [&mut serial]) {
-            continue;
+        // Stream the history dump as space in the transmit buffer becomes available.
+        if let Some(cursor) = history_dump.as_mut() {
+            let mut done = false;
+            while !done && serial.free() > util::StackString::capacity() {
+                if let Some(m) = history.next(cursor) {
+                    sprintln!(
+                        serial,
+                        "{}, {:.4}",
+                        m.time,
+                        contents.unit.convert(m.temperature)
+                    );
+                } else {
+                    if history.is_valid(cursor) {
+                        sprintln!(serial, "# history end");
+                    } else {
+                        sprintln!(serial, "# history changed during dump");
+                    }
+                    done = true;
+                }
+            }
+            if done {
+                history_dump = None;
+            }
         }
+
+        // Work through the storage task, as far as the transmit buffer allows.
+        if let Some(task) = storage_task.as_mut() {
+            let mut done = false;
+            if let Some(log) = run_log.as_mut() {
+                while !done && serial.free() > util::StackString::capacity() {
+                    match task {
+                        StorageTask::Runs(scan) => match log.scan_runs(scan, 64) {
+                            Ok(runlog::Scan::Item(r)) => {
+                                sprintln!(
+                                    serial,
+                                    "# run {} start {} ms samples {}",
+                                    r.run,
+                                    r.start,
+                                    r.samples
+                                );
+                            }
+                            Ok(runlog::Scan::Pending) => break,
+                            Ok(runlog::Scan::Done) => {
+                                sprintln!(serial, "# runs end");
+                                done = true;
+                            }
+                            Err(e) => {
+                                sprintln!(serial, "# runs failed {:?}", e);
+                                done = true;
+                            }
+                        },
+                        StorageTask::Download(scan) => match log.scan_samples(scan, 64) {
+                            Ok(runlog::Scan::Item(m)) => {
+                                sprintln!(
+                                    serial,
+                                    "{}, {:.4}",
+                                    m.time,
+                                    contents.unit.convert(m.temperature)
+                                );
+                            }
+                            Ok(runlog::Scan::Pending) => break,
+                            Ok(runlog::Scan::Done) => {
+                                sprintln!(serial, "# download end");
+                                done = true;
+                            }
+                            Err(e) => {
+                                sprintln!(serial, "# download failed {:?}", e);
+                                done = true;
+                            }
+                        },
+                        // One sector per iteration, each takes tens of milliseconds.
+                        StorageTask::Erase => match log.erase_step() {
+                            Ok(false) => break,
+                            Ok(true) => {
+                                sprintln!(serial, "# storage erased");
+                                if let Err(e) = log.start_run(clock::millis()) {
+                                    sprintln!(serial, "# storage run failed {:?}", e);
+                                }
+                                done = true;
+                            }
+                            Err(e) => {
+                                sprintln!(serial, "# storage erase failed {:?}", e);
+                                done = true;
+                            }
+                        },
+                    }
+                }
+            } else {
+                sprintln!(serial, "# no storage found");
+                done = true;
+            }
+            if done {
+                storage_task = None;
+            }
+        }
+
+        // The relay follows the controller, which cuts it off once the readings stop arriving.
+        if let Some(cutoff) = control.service(now) {
+            sprintln!(serial, "# control cutoff {}", cutoff.name());
+        }
+        if control.relay(now) {
+            ssr.set_high();
+        } else {
+            ssr.set_low();
+        }
+        if let Some(trip) = interlock.service(now) {
+            sprintln!(serial, "# interlock latched {}", trip.name());
+        }
+        if interlock.is_ok() {
+            interlock_pin.set_high();
+        } else {
+            interlock_pin.set_low();
+        }
+
+        if button_task.due(now) {
+            match debouncer.update(now, button::last_edge(), button::is_pressed()) {
+                // A press that wakes the display does nothing else.
+                Some(button::ButtonEvent::Short) => match disp.wake(now) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        if let Err(e) = disp.next_page() {
+                            sprintln!(serial, "# disp page: {:?}", e);
+                        }
+                    }
+                    Err(e) => sprintln!(serial, "# disp wake: {:?}", e),
+                },
+                Some(button::ButtonEvent::Long) => {
+                    let _ = disp.wake(now);
+                    contents.stats.reset(now);
+                    sprintln!(serial, "# stats reset");
+                    if let Err(e) = disp.update(&contents) {
+                        sprintln!(serial, "# disp update: {:?}", e);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        if display_task.due(now) {
+            if let Err(e) = disp.service(now) {
+                sprintln!(serial, "# disp service: {:?}", e);
+            }
+            let indicators = display::Indicators {
+                usb: serial.connected(),
+                logging: run_log.as_ref().is_some_and(|log| log.is_running()),
+                low_power: disp.is_dimmed(),
+                blanked: display::Indicators::blanked_at(now),
+            };
+            if indicators != contents.indicators {
+                contents.indicators = indicators;
+                if let Err(e) = disp.update(&contents) {
+                    sprintln!(serial, "# disp update: {:?}", e);
+                }
+            }
+            // Only queue more once the previous writes are out, the queue is then never full. After
+            // a failure this goes ahead, the next write moves the queue to the blocking driver.
+            if i2c_dma::is_idle()
+                && let Err(e) = disp.update_partial()
+            {
+                sprintln!(serial, "# update failed: {:?}", e);
+            }
+            if i2c_dma::failed() && !reported_dma_failure {
+                sprintln!(serial, "# disp dma failed; using blocking i2c");
+                reported_dma_failure = true;
+            }
+        }
+
+        if jitter_task.due(now) {
+            // Lateness of the sample task against its schedule, in milliseconds.
+            sprintln!(
+                serial,
+                "# sensor lateness max {} ms",
+                sensor_task.max_lateness()
+            );
+            sensor_task.reset_lateness();
+
+            // Repeat a stale reading, the transition may have been missed.
+            if matches!(contents.status, display::InternalStatus::Stale) {
+                sprintln!(
+                    serial,
+                    "# reading stale {} ms",
+                    clock::millis().wrapping_sub(contents.time)
+                );
+            }
+
+            // Repeat the unit of the log, for hosts that started reading after it was changed.
+            sprintln!(serial, "# unit {}", contents.unit.suffix());
+
+            // Report lines lost while the host wasn't reading, this line itself may also be lost
+            // but the count is kept until it makes it through.
+            let dropped = serial.dropped();
+            if dropped != reported_dropped {
+                sprintln!(serial, "# tx dropped {} lines", dropped);
+                if serial.dropped() == dropped {
+                    reported_dropped = dropped;
+                }
+            }
+        }
+
+        watchdog.feed();
+        scheduler::idle();
     }
 }
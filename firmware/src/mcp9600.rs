@@ -2,6 +2,8 @@
 use embedded_hal::i2c::I2c;
 
 pub const ADAFRUIT_MCP9600_ADDR: u8 = 0x67;
+/// Temperature in degrees C of the least significant bit of the hot junction register.
+pub const TEMPERATURE_RESOLUTION: f32 = 0.0625;
 pub struct TemperatureSensorDriver<I2C> {
     i2c: I2C,
     address: u8,
@@ -9,7 +11,7 @@ pub struct TemperatureSensorDriver<I2C> {
 
 fn hot_junction_to_temp(upper: u8, lower: u8) -> f32 {
     let v = i16::from_be_bytes([upper, lower]);
-    v as f32 * 0.0625
+    v as f32 * TEMPERATURE_RESOLUTION
 }
 
 #[cfg(test)]
@@ -83,6 +85,9 @@ impl HotJunctionRegister {
         hot_junction_to_temp(self.upper, self.lower)
     }
 }
+/// The cold junction (ambient) temperature uses the same encoding as the hot junction.
+pub type ColdJunctionRegister = HotJunctionRegister;
+
 impl core::fmt::Debug for HotJunctionRegister {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "0x{:0>2x} 0x{:0>2x}", self.upper, self.lower)
@@ -92,6 +97,7 @@ impl core::fmt::Debug for HotJunctionRegister {
 pub const REG_DEVICE_ID: u8 = 0b0010_0000;
 pub const REG_SENSOR_CONFIG: u8 = 0b0000_0101;
 pub const REG_HOT_JUNCTION: u8 = 0b0000_0000;
+pub const REG_COLD_JUNCTION: u8 = 0b0000_0010;
 pub const REG_STATUS: u8 = 0b0000_0100;
 
 impl<I2C: I2c> TemperatureSensorDriver<I2C> {
@@ -126,6 +132,13 @@ impl<I2C: I2c> TemperatureSensorDriver<I2C> {
         Ok(HotJunctionRegister::from_u8(tmp[0], tmp[1]))
     }
 
+    /// Read the cold junction temperature, the temperature of the sensor itself.
+    pub fn read_cold_junction(&mut self) -> Result<ColdJunctionRegister, I2C::Error> {
+        let mut tmp = [0u8, 0u8];
+        self.write_read(&[REG_COLD_JUNCTION], &mut tmp)?;
+        Ok(ColdJunctionRegister::from_u8(tmp[0], tmp[1]))
+    }
+
     /// Read the status register.
     pub fn read_status(&mut self) -> Result<StatusRegister, I2C::Error> {
         let mut tmp = [0u8];
@@ -0,0 +1,310 @@
+#![allow(unsafe_code)]
+//! Panic handler that reports the panic, instead of halting with a stale temperature on display.
+//!
+//! The message goes into a record in RAM that isn't initialised at boot, such that it survives the
+//! reset that follows. The display shows it for a few seconds before that reset, and after the
+//! boot it is reported on the serial port.
+use core::convert::Infallible;
+use core::fmt;
+use core::mem::MaybeUninit;
+
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::mono_font::iso_8859_9::FONT_5X7;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Baseline, Text};
+
+/// Longest message that is kept, longer ones are cut off.
+pub const MESSAGE_CAPACITY: usize = 160;
+/// Marks an intact record, "panc".
+const MAGIC: u32 = 0x7061_6e63;
+
+/// A panic message, with what it takes to tell it apart from the random contents of RAM after
+/// power up.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Record {
+    magic: u32,
+    len: u32,
+    checksum: u32,
+    message: [u8; MESSAGE_CAPACITY],
+}
+
+impl Record {
+    /// An intact record with an empty message.
+    pub const fn new() -> Self {
+        Self {
+            magic: MAGIC,
+            len: 0,
+            checksum: checksum(&[]),
+            message: [0; MESSAGE_CAPACITY],
+        }
+    }
+
+    /// The message, none unless the record is intact and holds one.
+    pub fn message(&self) -> Option<&str> {
+        let message = self.message.get(..self.len as usize)?;
+        if self.magic != MAGIC || self.checksum != checksum(message) || message.is_empty() {
+            return None;
+        }
+        core::str::from_utf8(message).ok()
+    }
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// FNV-1a.
+const fn checksum(bytes: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u32).wrapping_mul(0x0100_0193);
+        i += 1;
+    }
+    hash
+}
+
+/// Appends to the message, as far as it fits. Commas and line breaks are replaced, such that the
+/// message can be reported as a diagnostic line.
+impl fmt::Write for Record {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut len = self.len as usize;
+        for c in s.chars() {
+            let c = match c {
+                ',' => ';',
+                '\n' | '\r' => ' ',
+                c => c,
+            };
+            if len + c.len_utf8() > MESSAGE_CAPACITY {
+                break;
+            }
+            c.encode_utf8(&mut self.message[len..]);
+            len += c.len_utf8();
+        }
+        self.len = len as u32;
+        self.checksum = checksum(&self.message[..len]);
+        Ok(())
+    }
+}
+
+/// Split `text` in lines of at most `columns` characters.
+pub fn lines(text: &str, columns: usize) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    core::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest
+            .char_indices()
+            .nth(columns)
+            .map_or(rest.len(), |(i, _)| i);
+        let (line, tail) = rest.split_at(end);
+        rest = tail;
+        Some(line)
+    })
+}
+
+/// A page of 8 rows of the display, one byte per column like in the memory of the controller.
+pub struct PageBuffer {
+    width: u32,
+    columns: [u8; 128],
+}
+
+impl PageBuffer {
+    pub fn new(width: u32) -> Self {
+        Self {
+            width: width.min(128),
+            columns: [0; 128],
+        }
+    }
+
+    pub fn columns(&self) -> &[u8] {
+        &self.columns[..self.width as usize]
+    }
+}
+
+impl DrawTarget for PageBuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(p, color) in pixels {
+            if (0..self.width as i32).contains(&p.x) && (0..8).contains(&p.y) {
+                let bit = 1 << p.y;
+                if color.is_on() {
+                    self.columns[p.x as usize] |= bit;
+                } else {
+                    self.columns[p.x as usize] &= !bit;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for PageBuffer {
+    fn size(&self) -> Size {
+        Size::new(self.width, 8)
+    }
+}
+
+/// Render a line of text into a page.
+pub fn render_line(text: &str, page: &mut PageBuffer) {
+    let style = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
+    let _ = Text::with_baseline(text, Point::zero(), style, Baseline::Top).draw(page);
+}
+
+/// Characters per line of the display.
+pub fn display_columns(width: u32) -> usize {
+    (width / FONT_5X7.character_size.width) as usize
+}
+
+#[unsafe(link_section = ".uninit.panic")]
+static mut RECORD: MaybeUninit<Record> = MaybeUninit::uninit();
+
+/// The record of a panic before the last reset, its message is none if there wasn't one. The
+/// record is cleared, such that the panic is only reported once.
+pub fn take() -> Record {
+    let record = unsafe { (&raw const RECORD).cast::<Record>().read_volatile() };
+    unsafe {
+        (&raw mut RECORD)
+            .cast::<Record>()
+            .write_volatile(Record::new())
+    };
+    record
+}
+
+#[cfg(not(target_os = "linux"))]
+mod handler {
+    use core::fmt::Write;
+    use core::panic::PanicInfo;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use ssd1306::size::DisplaySize;
+    use stm32f1xx_hal::pac;
+
+    use super::*;
+    use crate::controller::{self, Controller, Panel};
+    use crate::i2c_dma::{self, PanicInterface};
+
+    /// Rounds of showing the message before the reset, each feeds the watchdog.
+    const SHOW_ROUNDS: u32 = 30;
+    /// About 100 ms at 48 MHz, longer when the panic came before the clocks were configured.
+    const ROUND_CYCLES: u32 = 4_800_000;
+
+    static PANICKING: AtomicBool = AtomicBool::new(false);
+
+    #[panic_handler]
+    fn panic(info: &PanicInfo) -> ! {
+        cortex_m::interrupt::disable();
+        // Switch off the oven relay on PB0 and drop the interlock on PB1 before anything else, the
+        // upper half of BSRR resets pins.
+        let gpiob = unsafe { &*pac::GPIOB::ptr() };
+        gpiob
+            .bsrr()
+            .write(|w| unsafe { w.bits((1 << 16) | (1 << 17)) });
+        // A panic while handling one, just reset.
+        if PANICKING.swap(true, Ordering::Relaxed) {
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+
+        let mut record = Record::new();
+        if let Some(location) = info.location() {
+            let _ = write!(record, "{}:{} ", location.file(), location.line());
+        }
+        let _ = write!(record, "{}", info.message());
+        unsafe { (&raw mut RECORD).cast::<Record>().write_volatile(record) };
+
+        if let Some(message) = record.message() {
+            let _ = show(message);
+        }
+
+        // The watchdog can't be stopped, keep feeding it while the message is shown.
+        let iwdg = unsafe { &*pac::IWDG::ptr() };
+        for _ in 0..SHOW_ROUNDS {
+            iwdg.kr().write(|w| w.key().reset());
+            cortex_m::asm::delay(ROUND_CYCLES);
+        }
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+
+    /// Show the message on the display, below a heading.
+    fn show(message: &str) -> Option<()> {
+        type PanelSize = <Panel<PanicInterface> as Controller>::Size;
+        let mut panel = controller::panel(i2c_dma::panic_interface()?);
+        panel.init().ok()?;
+        let width = PanelSize::WIDTH as u32;
+        let heading = core::iter::once("panic");
+        let text = heading.chain(lines(message, display_columns(width)));
+        for (page, line) in (0..PanelSize::HEIGHT / 8).zip(text) {
+            let mut buffer = PageBuffer::new(width);
+            render_line(line, &mut buffer);
+            panel.draw_page(page, 0, buffer.columns()).ok()?;
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn test_record() {
+        let mut record = Record::new();
+        assert_eq!(record.message(), None);
+        let detail = "a, b\nc";
+        write!(record, "src/lib.rs:93 {detail}").unwrap();
+        assert_eq!(record.message(), Some("src/lib.rs:93 a; b c"));
+
+        // Anything but an intact record is ignored.
+        let mut damaged = record;
+        damaged.message[0] = b'x';
+        assert_eq!(damaged.message(), None);
+        let mut damaged = record;
+        damaged.len = u32::MAX;
+        assert_eq!(damaged.message(), None);
+        let mut damaged = record;
+        damaged.magic = 0;
+        assert_eq!(damaged.message(), None);
+
+        // Long messages are cut off, at a character.
+        let mut record = Record::new();
+        for _ in 0..MESSAGE_CAPACITY {
+            write!(record, "é").unwrap();
+        }
+        let message = record.message().unwrap();
+        assert_eq!(message.len(), MESSAGE_CAPACITY);
+        assert_eq!(message.chars().count(), MESSAGE_CAPACITY / 2);
+    }
+
+    #[test]
+    fn test_lines() {
+        let l: Vec<_> = lines("abcdefg", 3).collect();
+        assert_eq!(l, ["abc", "def", "g"]);
+        let l: Vec<_> = lines("ééé", 2).collect();
+        assert_eq!(l, ["éé", "é"]);
+        assert_eq!(lines("", 3).count(), 0);
+        assert_eq!(display_columns(128), 25);
+    }
+
+    #[test]
+    fn test_page_buffer() {
+        let mut page = PageBuffer::new(64);
+        render_line("panic", &mut page);
+        assert_eq!(page.columns().len(), 64);
+        // Five characters of five columns, the rest stays clear.
+        assert!(page.columns()[..25].iter().any(|c| *c != 0));
+        assert!(page.columns()[25..].iter().all(|c| *c == 0));
+        // The font is seven rows high, the bottom row of the page is clear.
+        assert!(page.columns().iter().all(|c| c & 0x80 == 0));
+    }
+}
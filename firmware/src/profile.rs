@@ -0,0 +1,177 @@
+//! Temperature profiles for reflow soldering, followed live on the display during a run.
+//!
+//! A profile is a list of points in time and temperature, the target temperature is interpolated
+//! linearly between them. Each point also names the phase of the segment that ends at it.
+
+/// The phases of a reflow run, `Done` once the profile has ended.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Phase {
+    Preheat,
+    Soak,
+    Reflow,
+    Cool,
+    Done,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Preheat => "preheat",
+            Phase::Soak => "soak",
+            Phase::Reflow => "reflow",
+            Phase::Cool => "cool",
+            Phase::Done => "done",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProfilePoint {
+    /// Seconds since the start of the run.
+    pub time: u32,
+    /// Target temperature in Celsius.
+    pub temperature: f32,
+    /// Phase of the segment that ends at this point.
+    pub phase: Phase,
+}
+
+const fn point(time: u32, temperature: f32, phase: Phase) -> ProfilePoint {
+    ProfilePoint {
+        time,
+        temperature,
+        phase,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Profile {
+    /// Name to select the profile with, in lower case.
+    pub name: &'static str,
+    /// At least one point, in order of time.
+    pub points: &'static [ProfilePoint],
+}
+
+/// Chip Quik TS391AX50 SAC305 solder paste, as in `analysis/plot.py`.
+pub static TS391AX50: Profile = Profile {
+    name: "ts391ax50",
+    points: &[
+        point(0, 25.0, Phase::Preheat),
+        point(30, 100.0, Phase::Preheat),
+        point(120, 150.0, Phase::Soak),
+        point(150, 183.0, Phase::Reflow),
+        point(210, 235.0, Phase::Reflow),
+        point(240, 183.0, Phase::Cool),
+    ],
+};
+
+/// The known profiles, the first is the default.
+pub static PROFILES: [&Profile; 1] = [&TS391AX50];
+
+impl Profile {
+    pub fn find(name: &str) -> Option<&'static Profile> {
+        PROFILES.iter().copied().find(|p| p.name == name)
+    }
+
+    /// Length of the profile in milliseconds.
+    pub fn duration_ms(&self) -> u32 {
+        self.points.last().map_or(0, |p| p.time * 1000)
+    }
+
+    /// The point at the end of the segment at `elapsed` milliseconds, and its index.
+    fn segment(&self, elapsed: u32) -> Option<(usize, &ProfilePoint)> {
+        self.points
+            .iter()
+            .enumerate()
+            .find(|(_, p)| p.time * 1000 >= elapsed)
+    }
+
+    /// The target temperature at `elapsed` milliseconds, none after the end of the profile.
+    pub fn target(&self, elapsed: u32) -> Option<f32> {
+        let (i, end) = self.segment(elapsed)?;
+        let Some(start) = i.checked_sub(1).map(|i| &self.points[i]) else {
+            return Some(end.temperature);
+        };
+        let fraction =
+            (elapsed - start.time * 1000) as f32 / ((end.time - start.time) * 1000) as f32;
+        Some(start.temperature + (end.temperature - start.temperature) * fraction)
+    }
+
+    pub fn phase(&self, elapsed: u32) -> Phase {
+        self.segment(elapsed).map_or(Phase::Done, |(_, p)| p.phase)
+    }
+}
+
+/// A run following a profile.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Run {
+    pub profile: &'static Profile,
+    /// Start time in milliseconds.
+    pub start: u32,
+}
+
+impl Run {
+    pub fn new(profile: &'static Profile, start: u32) -> Self {
+        Self { profile, start }
+    }
+
+    /// Milliseconds since the start at `now`, zero for times before the start; such as that of
+    /// the last sample when the run was just started.
+    pub fn elapsed(&self, now: u32) -> u32 {
+        (now.wrapping_sub(self.start) as i32).max(0) as u32
+    }
+
+    pub fn target(&self, now: u32) -> Option<f32> {
+        self.profile.target(self.elapsed(now))
+    }
+
+    pub fn phase(&self, now: u32) -> Phase {
+        self.profile.phase(self.elapsed(now))
+    }
+
+    /// The fraction of the profile that has passed, from 0 to 1.
+    pub fn progress(&self, now: u32) -> f32 {
+        (self.elapsed(now) as f32 / self.profile.duration_ms().max(1) as f32).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let p = &TS391AX50;
+        assert_eq!(p.target(0), Some(25.0));
+        assert_eq!(p.target(15_000), Some(62.5));
+        assert_eq!(p.target(30_000), Some(100.0));
+        assert_eq!(p.target(135_000), Some(166.5));
+        assert_eq!(p.target(240_000), Some(183.0));
+        assert_eq!(p.target(240_001), None);
+
+        assert_eq!(p.phase(0), Phase::Preheat);
+        assert_eq!(p.phase(30_000), Phase::Preheat);
+        assert_eq!(p.phase(30_001), Phase::Soak);
+        assert_eq!(p.phase(180_000), Phase::Reflow);
+        assert_eq!(p.phase(220_000), Phase::Cool);
+        assert_eq!(p.phase(250_000), Phase::Done);
+
+        assert_eq!(Profile::find("ts391ax50"), Some(&TS391AX50));
+        assert_eq!(Profile::find("foo"), None);
+    }
+
+    #[test]
+    fn test_run() {
+        let run = Run::new(&TS391AX50, 1000);
+        // A sample from just before the start counts as the start.
+        assert_eq!(run.elapsed(900), 0);
+        assert_eq!(run.phase(900), Phase::Preheat);
+        assert_eq!(run.target(31_000), Some(100.0));
+        assert_eq!(run.progress(121_000), 0.5);
+        assert_eq!(run.progress(1_000_000), 1.0);
+
+        // Across the wrap around of the clock.
+        let run = Run::new(&TS391AX50, u32::MAX - 4999);
+        assert_eq!(run.elapsed(10_000), 15_000);
+        assert_eq!(run.target(10_000), Some(62.5));
+    }
+}
@@ -0,0 +1,89 @@
+//! Cause of the last reset, from the flags in the RCC control/status register.
+//!
+//! The flags accumulate over resets until they are cleared, so they are read and cleared once at
+//! boot. A power on reset also sets the pin flag, as the reset pin is held low while powering up.
+use core::fmt;
+
+use stm32f1xx_hal::pac;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ResetCause {
+    pub pin: bool,
+    pub power_on: bool,
+    pub software: bool,
+    pub independent_watchdog: bool,
+    pub window_watchdog: bool,
+    pub low_power: bool,
+}
+
+impl ResetCause {
+    /// Decode the flags of the CSR register.
+    pub fn from_csr(csr: u32) -> Self {
+        let flag = |bit: u32| csr & (1 << bit) != 0;
+        Self {
+            pin: flag(26),
+            power_on: flag(27),
+            software: flag(28),
+            independent_watchdog: flag(29),
+            window_watchdog: flag(30),
+            low_power: flag(31),
+        }
+    }
+
+    pub fn is_watchdog(&self) -> bool {
+        self.independent_watchdog || self.window_watchdog
+    }
+}
+
+/// The causes separated by spaces, `unknown` without any.
+impl fmt::Display for ResetCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (self.power_on, "power"),
+            (self.pin, "pin"),
+            (self.software, "software"),
+            (self.independent_watchdog, "watchdog"),
+            (self.window_watchdog, "window watchdog"),
+            (self.low_power, "low power"),
+        ];
+        let mut any = false;
+        for (_, name) in names.iter().filter(|(set, _)| *set) {
+            if any {
+                f.write_str(" ")?;
+            }
+            f.write_str(name)?;
+            any = true;
+        }
+        if !any {
+            f.write_str("unknown")?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the cause of the last reset and clear the flags, such that the next reset reports its own.
+pub fn take(rcc: &pac::RCC) -> ResetCause {
+    let cause = ResetCause::from_csr(rcc.csr().read().bits());
+    rcc.csr().modify(|_, w| w.rmvf().set_bit());
+    cause
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_cause() {
+        // Power on, with the LSI running.
+        let cause = ResetCause::from_csr(0x0c00_0003);
+        assert!(cause.power_on && cause.pin);
+        assert!(!cause.is_watchdog());
+        assert_eq!(format!("{cause}"), "power pin");
+
+        let cause = ResetCause::from_csr(0x2400_0000);
+        assert!(cause.is_watchdog());
+        assert_eq!(format!("{cause}"), "pin watchdog");
+
+        assert_eq!(format!("{}", ResetCause::from_csr(0)), "unknown");
+    }
+}
@@ -0,0 +1,144 @@
+#![allow(unsafe_code)]
+//! Lock-free single producer, single consumer byte ring buffer.
+//!
+//! The producer (main loop) only moves `head`, the consumer (an interrupt) only moves `tail`, so
+//! neither side ever needs a critical section.
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct RingBuffer<const N: usize> {
+    buffer: UnsafeCell<[u8; N]>,
+    /// Total number of bytes ever pushed, wraps.
+    head: AtomicUsize,
+    /// Total number of bytes ever consumed, wraps.
+    tail: AtomicUsize,
+}
+
+// The producer and consumer never touch the same bytes, see push and peek.
+unsafe impl<const N: usize> Sync for RingBuffer<N> {}
+
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        // The counters wrap, which only lines up with the indices for powers of two.
+        const { assert!(N.is_power_of_two()) };
+        Self {
+            buffer: UnsafeCell::new([0; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Number of bytes waiting to be consumed.
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        head.wrapping_sub(tail)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of bytes that can be pushed.
+    pub fn free(&self) -> usize {
+        N - self.len()
+    }
+
+    /// Producer side, push as many bytes as fit and return how many were pushed.
+    pub fn push(&self, data: &[u8]) -> usize {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        let free = N - head.wrapping_sub(tail);
+        let count = data.len().min(free);
+        let buffer = self.buffer.get() as *mut u8;
+        for (i, b) in data[..count].iter().enumerate() {
+            // Only writes into the free region, which the consumer doesn't read.
+            unsafe {
+                *buffer.add(head.wrapping_add(i) % N) = *b;
+            }
+        }
+        self.head.store(head.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// Producer side, push all of `data` or nothing at all if it doesn't fit.
+    pub fn push_all(&self, data: &[u8]) -> bool {
+        if data.len() > self.free() {
+            return false;
+        }
+        self.push(data) == data.len()
+    }
+
+    /// Consumer side, the oldest contiguous run of pending bytes.
+    ///
+    /// This may be shorter than `len()` if the pending data wraps around the end of the buffer.
+    pub fn peek(&self) -> &[u8] {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        let start = tail % N;
+        let count = head.wrapping_sub(tail).min(N - start);
+        // The producer doesn't write into the pending region until it is consumed.
+        unsafe { core::slice::from_raw_parts((self.buffer.get() as *const u8).add(start), count) }
+    }
+
+    /// Consumer side, drop `count` bytes previously returned by `peek`.
+    pub fn consume(&self, count: usize) {
+        let tail = self.tail.load(Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(count), Ordering::Release);
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_ring_buffer() {
+        let ring = RingBuffer::<8>::new();
+        assert!(ring.is_empty());
+        assert_eq!(ring.push(b"abcde"), 5);
+        assert_eq!(ring.len(), 5);
+        assert_eq!(ring.free(), 3);
+        assert_eq!(ring.peek(), b"abcde");
+        ring.consume(3);
+        assert_eq!(ring.peek(), b"de");
+
+        // Only partially fits, and wraps around the end.
+        assert_eq!(ring.push(b"fghijkl"), 6);
+        assert_eq!(ring.free(), 0);
+        assert_eq!(ring.push(b"x"), 0);
+        assert_eq!(ring.peek(), b"defgh");
+        ring.consume(5);
+        assert_eq!(ring.peek(), b"ijk");
+        ring.consume(3);
+        assert!(ring.is_empty());
+        assert_eq!(ring.peek(), b"");
+
+        // All or nothing.
+        assert!(ring.push_all(b"abcdef"));
+        assert!(!ring.push_all(b"ghi"));
+        assert_eq!(ring.len(), 6);
+        assert!(ring.push_all(b"gh"));
+        assert_eq!(ring.free(), 0);
+        ring.consume(8);
+
+        // Counters wrapping around usize.
+        let ring = RingBuffer::<4>::new();
+        ring.head.store(usize::MAX - 1, Ordering::Relaxed);
+        ring.tail.store(usize::MAX - 1, Ordering::Relaxed);
+        assert_eq!(ring.push(b"abc"), 3);
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.peek(), b"ab");
+        ring.consume(2);
+        assert_eq!(ring.peek(), b"c");
+    }
+}
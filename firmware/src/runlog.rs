@@ -0,0 +1,437 @@
+//! Append-only log of runs on non-volatile storage.
+//!
+//! The log is a sequence of 8 byte records starting at address zero, it ends at the first erased
+//! record. Every boot starts a new run with a run record, followed by the sample records of that
+//! run. Runs are numbered by their order in the log, starting at zero.
+//!
+//! | bytes  | run record          | sample record        |
+//! |--------|---------------------|----------------------|
+//! | 0      | `b'R'`              | `b'S'`               |
+//! | 1      | reserved, 0         | reserved, 0          |
+//! | 2..4   | reserved, 0         | temperature, i16     |
+//! | 4..8   | start time, u32     | time, u32            |
+//!
+//! Integers are little endian, temperatures in the sensor's 0.0625 C steps and times are
+//! milliseconds since boot.
+
+use crate::display::Measurement;
+use crate::mcp9600::TEMPERATURE_RESOLUTION;
+use crate::storage::Storage;
+
+pub const RECORD_SIZE: u32 = 8;
+const TAG_RUN: u8 = b'R';
+const TAG_SAMPLE: u8 = b'S';
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Record {
+    Run { time: u32 },
+    Sample { time: u32, raw: i16 },
+}
+
+impl Record {
+    pub fn sample(time: u32, temperature: f32) -> Self {
+        Record::Sample {
+            time,
+            raw: (temperature / TEMPERATURE_RESOLUTION) as i16,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; RECORD_SIZE as usize] {
+        let (tag, value, time) = match *self {
+            Record::Run { time } => (TAG_RUN, 0, time),
+            Record::Sample { time, raw } => (TAG_SAMPLE, raw, time),
+        };
+        let v = value.to_le_bytes();
+        let t = time.to_le_bytes();
+        [tag, 0, v[0], v[1], t[0], t[1], t[2], t[3]]
+    }
+
+    /// Decode a record, returns None for erased or unknown records.
+    pub fn decode(data: &[u8; RECORD_SIZE as usize]) -> Option<Self> {
+        let value = i16::from_le_bytes([data[2], data[3]]);
+        let time = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        match data[0] {
+            TAG_RUN => Some(Record::Run { time }),
+            TAG_SAMPLE => Some(Record::Sample { time, raw: value }),
+            _ => None,
+        }
+    }
+
+    fn is_erased(data: &[u8; RECORD_SIZE as usize]) -> bool {
+        data.iter().all(|v| *v == 0xff)
+    }
+}
+
+#[derive(Debug)]
+pub enum RunLogError<E> {
+    Storage(E),
+    /// No space left for another record.
+    Full,
+    /// Samples can only be appended after a run is started.
+    NoRun,
+}
+
+/// Summary of a run in the log.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RunSummary {
+    pub run: u32,
+    pub start: u32,
+    pub samples: u32,
+}
+
+/// Result of a step of an incremental scan through the log.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Scan<T> {
+    Item(T),
+    /// The budget for this step ran out, call again to continue.
+    Pending,
+    Done,
+}
+
+/// Scan position for listing the runs.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RunScan {
+    index: u32,
+    runs: u32,
+    current: Option<RunSummary>,
+}
+
+/// Scan position for reading the samples of one run.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SampleScan {
+    run: u32,
+    index: u32,
+    runs: u32,
+    in_run: bool,
+}
+
+impl SampleScan {
+    pub fn new(run: u32) -> Self {
+        Self {
+            run,
+            index: 0,
+            runs: 0,
+            in_run: false,
+        }
+    }
+}
+
+pub struct RunLog<S: Storage> {
+    storage: S,
+    /// Index of the first erased record.
+    end: u32,
+    running: bool,
+}
+
+impl<S: Storage> RunLog<S> {
+    /// Open the log on this storage, finding where it ends.
+    pub fn new(storage: S) -> Result<Self, RunLogError<S::Error>> {
+        let mut log = Self {
+            storage,
+            end: 0,
+            running: false,
+        };
+        // Records are only ever appended, so everything before the end is written and everything
+        // from the end on is erased; binary search for it.
+        let (mut low, mut high) = (0, log.capacity());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if Record::is_erased(&log.read_raw(middle)?) {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+        log.end = low;
+        Ok(log)
+    }
+
+    /// Total number of records that fit in the storage.
+    pub fn capacity(&self) -> u32 {
+        self.storage.capacity() / RECORD_SIZE
+    }
+
+    /// Number of records in use.
+    pub fn len(&self) -> u32 {
+        self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end == 0
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    fn read_raw(
+        &mut self,
+        index: u32,
+    ) -> Result<[u8; RECORD_SIZE as usize], RunLogError<S::Error>> {
+        let mut data = [0u8; RECORD_SIZE as usize];
+        self.storage
+            .read(index * RECORD_SIZE, &mut data)
+            .map_err(RunLogError::Storage)?;
+        Ok(data)
+    }
+
+    /// Read the record at index.
+    pub fn read(&mut self, index: u32) -> Result<Option<Record>, RunLogError<S::Error>> {
+        Ok(Record::decode(&self.read_raw(index)?))
+    }
+
+    fn append(&mut self, record: Record) -> Result<(), RunLogError<S::Error>> {
+        if self.end >= self.capacity() {
+            return Err(RunLogError::Full);
+        }
+        self.storage
+            .write(self.end * RECORD_SIZE, &record.encode())
+            .map_err(RunLogError::Storage)?;
+        self.end += 1;
+        Ok(())
+    }
+
+    /// Start a new run, subsequent samples belong to this.
+    pub fn start_run(&mut self, time: u32) -> Result<(), RunLogError<S::Error>> {
+        self.append(Record::Run { time })?;
+        self.running = true;
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn append_sample(
+        &mut self,
+        time: u32,
+        temperature: f32,
+    ) -> Result<(), RunLogError<S::Error>> {
+        if !self.running {
+            return Err(RunLogError::NoRun);
+        }
+        let result = self.append(Record::sample(time, temperature));
+        if let Err(RunLogError::Full) = result {
+            // The run ends when the storage is full.
+            self.running = false;
+        }
+        result
+    }
+
+    /// Erase the last sector in use, returns true once the log is empty.
+    ///
+    /// Erasing from the end keeps the log consistent if this is interrupted. This ends the
+    /// current run.
+    pub fn erase_step(&mut self) -> Result<bool, RunLogError<S::Error>> {
+        self.running = false;
+        if self.end == 0 {
+            return Ok(true);
+        }
+        let sector_size = self.storage.sector_size();
+        let last = self.end * RECORD_SIZE - 1;
+        let sector = last - last % sector_size;
+        self.storage
+            .erase_sector(sector)
+            .map_err(RunLogError::Storage)?;
+        self.end = sector / RECORD_SIZE;
+        Ok(self.end == 0)
+    }
+
+    /// List the runs, reading at most `budget` records per call.
+    pub fn scan_runs(
+        &mut self,
+        scan: &mut RunScan,
+        budget: u32,
+    ) -> Result<Scan<RunSummary>, RunLogError<S::Error>> {
+        for _ in 0..budget {
+            if scan.index >= self.end {
+                return Ok(match scan.current.take() {
+                    Some(run) => Scan::Item(run),
+                    None => Scan::Done,
+                });
+            }
+            let record = self.read(scan.index)?;
+            scan.index += 1;
+            match record {
+                Some(Record::Run { time }) => {
+                    let finished = scan.current.replace(RunSummary {
+                        run: scan.runs,
+                        start: time,
+                        samples: 0,
+                    });
+                    scan.runs += 1;
+                    if let Some(run) = finished {
+                        return Ok(Scan::Item(run));
+                    }
+                }
+                Some(Record::Sample { .. }) => {
+                    if let Some(run) = scan.current.as_mut() {
+                        run.samples += 1;
+                    }
+                }
+                None => {}
+            }
+        }
+        Ok(Scan::Pending)
+    }
+
+    /// Read the samples of a run, reading at most `budget` records per call.
+    pub fn scan_samples(
+        &mut self,
+        scan: &mut SampleScan,
+        budget: u32,
+    ) -> Result<Scan<Measurement>, RunLogError<S::Error>> {
+        for _ in 0..budget {
+            if scan.index >= self.end {
+                return Ok(Scan::Done);
+            }
+            let record = self.read(scan.index)?;
+            scan.index += 1;
+            match record {
+                Some(Record::Run { .. }) => {
+                    if scan.in_run {
+                        // Reached the next run, nothing more to read.
+                        scan.index = u32::MAX;
+                        return Ok(Scan::Done);
+                    }
+                    scan.in_run = scan.runs == scan.run;
+                    scan.runs += 1;
+                }
+                Some(Record::Sample { time, raw }) if scan.in_run => {
+                    return Ok(Scan::Item(Measurement {
+                        time,
+                        temperature: raw as f32 * TEMPERATURE_RESOLUTION,
+                    }));
+                }
+                _ => {}
+            }
+        }
+        Ok(Scan::Pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::RamStorage;
+
+    fn runs<S: Storage>(log: &mut RunLog<S>) -> Vec<RunSummary> {
+        let mut scan = RunScan::default();
+        let mut result = vec![];
+        loop {
+            match log.scan_runs(&mut scan, 3).unwrap() {
+                Scan::Item(run) => result.push(run),
+                Scan::Pending => {}
+                Scan::Done => return result,
+            }
+        }
+    }
+
+    fn samples<S: Storage>(log: &mut RunLog<S>, run: u32) -> Vec<Measurement> {
+        let mut scan = SampleScan::new(run);
+        let mut result = vec![];
+        loop {
+            match log.scan_samples(&mut scan, 3).unwrap() {
+                Scan::Item(m) => result.push(m),
+                Scan::Pending => {}
+                Scan::Done => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn test_record_encoding() {
+        for r in [
+            Record::Run { time: 0x12345678 },
+            Record::sample(u32::MAX, -3.0625),
+            Record::sample(0, 1372.0),
+        ] {
+            assert_eq!(Record::decode(&r.encode()), Some(r));
+        }
+        assert_eq!(
+            Record::sample(1, 1.0).encode(),
+            [b'S', 0, 16, 0, 1, 0, 0, 0]
+        );
+        assert!(Record::is_erased(&[0xff; 8]));
+        assert_eq!(Record::decode(&[0xff; 8]), None);
+    }
+
+    #[test]
+    fn test_run_log() {
+        let mut log = RunLog::new(RamStorage::<256>::new(64)).unwrap();
+        assert!(log.is_empty());
+        assert_eq!(log.capacity(), 32);
+        assert!(matches!(log.append_sample(0, 1.0), Err(RunLogError::NoRun)));
+
+        log.start_run(100).unwrap();
+        log.append_sample(200, 20.0).unwrap();
+        log.append_sample(300, 20.5).unwrap();
+        log.start_run(10).unwrap();
+        log.start_run(20).unwrap();
+        for i in 0..5 {
+            log.append_sample(30 + i, -(i as f32)).unwrap();
+        }
+        assert_eq!(log.len(), 10);
+
+        // Reopening finds the end again.
+        let mut log = RunLog::new(log.storage).unwrap();
+        assert_eq!(log.len(), 10);
+        assert!(!log.is_running());
+
+        assert_eq!(
+            runs(&mut log),
+            vec![
+                RunSummary {
+                    run: 0,
+                    start: 100,
+                    samples: 2
+                },
+                RunSummary {
+                    run: 1,
+                    start: 10,
+                    samples: 0
+                },
+                RunSummary {
+                    run: 2,
+                    start: 20,
+                    samples: 5
+                },
+            ]
+        );
+        assert_eq!(
+            samples(&mut log, 0),
+            vec![
+                Measurement {
+                    time: 200,
+                    temperature: 20.0
+                },
+                Measurement {
+                    time: 300,
+                    temperature: 20.5
+                }
+            ]
+        );
+        assert_eq!(samples(&mut log, 1), vec![]);
+        assert_eq!(samples(&mut log, 2).len(), 5);
+        assert_eq!(samples(&mut log, 3), vec![]);
+
+        // Fill it up.
+        log.start_run(1000).unwrap();
+        while log.len() < log.capacity() {
+            log.append_sample(1000, 1.0).unwrap();
+        }
+        assert!(matches!(log.append_sample(0, 1.0), Err(RunLogError::Full)));
+        assert!(!log.is_running());
+        assert!(matches!(log.append_sample(0, 1.0), Err(RunLogError::NoRun)));
+
+        // Erasing happens a sector at a time, from the end.
+        assert!(!log.erase_step().unwrap());
+        assert_eq!(log.len(), 24);
+        assert!(!log.is_running());
+        let mut reopened = RunLog::new(log.storage).unwrap();
+        assert_eq!(reopened.len(), 24);
+        while !reopened.erase_step().unwrap() {}
+        assert!(reopened.is_empty());
+        assert_eq!(runs(&mut reopened), vec![]);
+    }
+}
@@ -0,0 +1,100 @@
+//! A small cooperative scheduler for the main loop.
+//!
+//! Tasks are periodic; each one tracks when it should run next and how late it actually ran. In
+//! between the core sleeps with WFI, TIM2 wakes it every millisecond and the USB interrupt wakes
+//! it whenever something happens on the bus.
+
+/// A task that should run every `period` milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Periodic {
+    period: u32,
+    next: u32,
+    /// Lateness of the most recent run in milliseconds.
+    lateness: u32,
+    /// Largest lateness since the last reset.
+    max_lateness: u32,
+}
+
+impl Periodic {
+    /// Create a task that is first due at `now`.
+    pub fn new(now: u32, period: u32) -> Self {
+        Self {
+            period,
+            next: now,
+            lateness: 0,
+            max_lateness: 0,
+        }
+    }
+
+    /// Returns true if the task should run at `now`, advancing the deadline if so.
+    pub fn due(&mut self, now: u32) -> bool {
+        let late = now.wrapping_sub(self.next);
+        if (late as i32) < 0 {
+            return false;
+        }
+        self.lateness = late;
+        self.max_lateness = self.max_lateness.max(late);
+        self.next = self.next.wrapping_add(self.period);
+        // If we fell behind by more than a period, don't try to catch up with a burst of runs.
+        if (now.wrapping_sub(self.next) as i32) >= 0 {
+            self.next = now.wrapping_add(self.period);
+        }
+        true
+    }
+
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+
+    pub fn lateness(&self) -> u32 {
+        self.lateness
+    }
+
+    pub fn max_lateness(&self) -> u32 {
+        self.max_lateness
+    }
+
+    pub fn reset_lateness(&mut self) {
+        self.max_lateness = 0;
+    }
+}
+
+/// Sleep until the next interrupt.
+pub fn idle() {
+    cortex_m::asm::wfi();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_periodic() {
+        let mut t = Periodic::new(100, 50);
+        assert!(t.due(100));
+        assert!(!t.due(100));
+        assert!(!t.due(149));
+        assert!(t.due(152));
+        assert_eq!(t.lateness(), 2);
+        // Deadline doesn't drift with the lateness.
+        assert!(!t.due(199));
+        assert!(t.due(200));
+        assert_eq!(t.lateness(), 0);
+        assert_eq!(t.max_lateness(), 2);
+        t.reset_lateness();
+        assert_eq!(t.max_lateness(), 0);
+
+        // Falling far behind runs once, not a burst.
+        assert!(t.due(1000));
+        assert_eq!(t.lateness(), 750);
+        assert!(!t.due(1001));
+        assert!(t.due(1050));
+
+        // Check wrapping.
+        let mut t = Periodic::new(u32::MAX - 10, 50);
+        assert!(t.due(u32::MAX - 10));
+        assert!(!t.due(u32::MAX));
+        assert!(!t.due(5));
+        assert!(t.due(39));
+        assert_eq!(t.lateness(), 0);
+    }
+}
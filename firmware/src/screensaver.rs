@@ -0,0 +1,121 @@
+//! Protect the OLED against burn in.
+//!
+//! The display is dimmed and later switched off after a period without activity, activity being a
+//! button press or an alert. The layout is also shifted by a pixel periodically, such that static
+//! text doesn't always light up the same pixels.
+use embedded_graphics::prelude::Point;
+
+/// Offsets the layout cycles through, such that it never moves more than a pixel from its origin.
+const OFFSETS: [Point; 4] = [
+    Point::new(0, 0),
+    Point::new(1, 0),
+    Point::new(1, 1),
+    Point::new(0, 1),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Power {
+    On,
+    Dimmed,
+    Off,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Contrast when on.
+    pub contrast: u8,
+    /// Contrast when dimmed.
+    pub dim_contrast: u8,
+    /// Inactivity before dimming in milliseconds, zero to never dim.
+    pub dim_after: u32,
+    /// Inactivity before switching off in milliseconds, zero to never switch off.
+    pub off_after: u32,
+    /// Period between shifts of the layout in milliseconds, zero to not shift.
+    pub shift_period: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            contrast: 0x5f,
+            dim_contrast: 0x00,
+            dim_after: 5 * 60 * 1000,
+            off_after: 30 * 60 * 1000,
+            shift_period: 60 * 1000,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ScreenSaver {
+    pub config: Config,
+    last_activity: u32,
+}
+
+impl ScreenSaver {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            last_activity: 0,
+        }
+    }
+
+    /// Record activity at `now`, this postpones dimming and switching off.
+    pub fn activity(&mut self, now: u32) {
+        self.last_activity = now;
+    }
+
+    /// The state the display should be in at `now`.
+    pub fn power(&self, now: u32) -> Power {
+        let idle = now.wrapping_sub(self.last_activity);
+        let (dim_after, off_after) = (self.config.dim_after, self.config.off_after);
+        if off_after != 0 && idle >= off_after {
+            Power::Off
+        } else if dim_after != 0 && idle >= dim_after {
+            Power::Dimmed
+        } else {
+            Power::On
+        }
+    }
+
+    /// The offset of the layout at `now`.
+    pub fn offset(&self, now: u32) -> Point {
+        match self.config.shift_period {
+            0 => OFFSETS[0],
+            period => OFFSETS[(now / period) as usize % OFFSETS.len()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_screensaver() {
+        let mut s = ScreenSaver::new(Config {
+            dim_after: 100,
+            off_after: 300,
+            shift_period: 50,
+            ..Default::default()
+        });
+        s.activity(1000);
+        assert_eq!(s.power(1099), Power::On);
+        assert_eq!(s.power(1100), Power::Dimmed);
+        assert_eq!(s.power(1300), Power::Off);
+        s.activity(1400);
+        assert_eq!(s.power(1401), Power::On);
+
+        assert_eq!(s.offset(0), Point::new(0, 0));
+        assert_eq!(s.offset(60), Point::new(1, 0));
+        assert_eq!(s.offset(199), Point::new(0, 1));
+        assert_eq!(s.offset(200), Point::new(0, 0));
+
+        // Disabled.
+        s.config.off_after = 0;
+        s.config.shift_period = 0;
+        assert_eq!(s.power(100_000), Power::Dimmed);
+        assert_eq!(s.offset(60), Point::new(0, 0));
+        s.config.dim_after = 0;
+        assert_eq!(s.power(100_000), Power::On);
+    }
+}
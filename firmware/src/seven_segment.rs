@@ -0,0 +1,117 @@
+//! Large seven segment digits, readable from across the room.
+//!
+//! The glyphs are drawn from their segments at compile time, into the same bitmap layout as the
+//! embedded-graphics fonts, so the font is used like any other `MonoFont`.
+use embedded_graphics::geometry::Size;
+use embedded_graphics::image::ImageRaw;
+use embedded_graphics::mono_font::mapping::StrGlyphMapping;
+use embedded_graphics::mono_font::{DecorationDimensions, MonoFont};
+
+const WIDTH: usize = 16;
+const HEIGHT: usize = 32;
+
+/// The characters in the font, anything else is drawn as a space.
+const GLYPHS: &str = " 0123456789-.";
+
+/// Segments of each glyph, bit 0 to 6 are segments a to g, bit 7 is the decimal point.
+const SEGMENTS: [u8; 13] = [
+    0x00, 0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f, 0x40, 0x80,
+];
+
+/// Pixels of segments a to g and the decimal point, as `(x0, y0, x1, y1)` with exclusive ends.
+const SEGMENT_AREAS: [(usize, usize, usize, usize); 8] = [
+    (4, 1, 12, 4),    // a, top.
+    (12, 4, 15, 15),  // b, top right.
+    (12, 18, 15, 29), // c, bottom right.
+    (4, 29, 12, 32),  // d, bottom.
+    (1, 18, 4, 29),   // e, bottom left.
+    (1, 4, 4, 15),    // f, top left.
+    (4, 15, 12, 18),  // g, middle.
+    (6, 28, 10, 32),  // Decimal point.
+];
+
+const ROW_BYTES: usize = WIDTH * SEGMENTS.len() / 8;
+
+/// All glyphs side by side, one bit per pixel with the most significant bit left.
+const GLYPH_DATA: [u8; ROW_BYTES * HEIGHT] = draw_glyphs();
+
+const fn draw_glyphs() -> [u8; ROW_BYTES * HEIGHT] {
+    let mut data = [0u8; ROW_BYTES * HEIGHT];
+    let mut glyph = 0;
+    while glyph < SEGMENTS.len() {
+        let mut segment = 0;
+        while segment < SEGMENT_AREAS.len() {
+            if SEGMENTS[glyph] & (1 << segment) != 0 {
+                let (x0, y0, x1, y1) = SEGMENT_AREAS[segment];
+                let mut y = y0;
+                while y < y1 {
+                    let mut x = glyph * WIDTH + x0;
+                    while x < glyph * WIDTH + x1 {
+                        data[y * ROW_BYTES + x / 8] |= 0x80 >> (x % 8);
+                        x += 1;
+                    }
+                    y += 1;
+                }
+            }
+            segment += 1;
+        }
+        glyph += 1;
+    }
+    data
+}
+
+/// Digits, minus sign and decimal point of 16x32 pixels.
+pub const FONT_SEVEN_SEGMENT_16X32: MonoFont = MonoFont {
+    image: ImageRaw::new(&GLYPH_DATA, (WIDTH * SEGMENTS.len()) as u32),
+    glyph_mapping: &StrGlyphMapping::new(GLYPHS, 0),
+    character_size: Size::new(WIDTH as u32, HEIGHT as u32),
+    character_spacing: 0,
+    baseline: HEIGHT as u32 - 1,
+    underline: DecorationDimensions::new(HEIGHT as u32 - 1, 1),
+    strikethrough: DecorationDimensions::new(HEIGHT as u32 / 2, 1),
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::mono_font::MonoTextStyle;
+    use embedded_graphics::pixelcolor::BinaryColor;
+    use embedded_graphics::prelude::*;
+    use embedded_graphics::text::{Baseline, Text};
+
+    fn lit(text: &str) -> usize {
+        let mut display = MockDisplay::<BinaryColor>::new();
+        let style = MonoTextStyle::new(&FONT_SEVEN_SEGMENT_16X32, BinaryColor::On);
+        Text::with_baseline(text, Point::zero(), style, Baseline::Top)
+            .draw(&mut display)
+            .unwrap();
+        display
+            .bounding_box()
+            .points()
+            .filter(|p| display.get_pixel(*p) == Some(BinaryColor::On))
+            .count()
+    }
+
+    #[test]
+    fn test_seven_segment() {
+        assert_eq!(GLYPHS.len(), SEGMENTS.len());
+        // Horizontal segments are 8x3, vertical ones 3x11.
+        assert_eq!(lit("1"), 2 * 33);
+        assert_eq!(lit("-"), 24);
+        assert_eq!(lit("8"), 3 * 24 + 4 * 33);
+        assert_eq!(lit("."), 16);
+        assert_eq!(lit("8.8"), 2 * lit("8") + lit("."));
+        // Anything outside the font is blank.
+        assert_eq!(lit(" x"), 0);
+
+        // The segments of a one are on the right.
+        let mut display = MockDisplay::<BinaryColor>::new();
+        let style = MonoTextStyle::new(&FONT_SEVEN_SEGMENT_16X32, BinaryColor::On);
+        Text::with_baseline("1", Point::zero(), style, Baseline::Top)
+            .draw(&mut display)
+            .unwrap();
+        assert_eq!(display.get_pixel(Point::new(13, 10)), Some(BinaryColor::On));
+        assert_eq!(display.get_pixel(Point::new(2, 10)), None);
+    }
+}
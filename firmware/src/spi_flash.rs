@@ -0,0 +1,168 @@
+//! Driver for SPI NOR flash chips, such as the W25Q series, using the common JEDEC command set.
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+
+use crate::storage::Storage;
+
+pub const CMD_PAGE_PROGRAM: u8 = 0x02;
+pub const CMD_READ: u8 = 0x03;
+pub const CMD_READ_STATUS: u8 = 0x05;
+pub const CMD_WRITE_ENABLE: u8 = 0x06;
+pub const CMD_SECTOR_ERASE: u8 = 0x20;
+pub const CMD_JEDEC_ID: u8 = 0x9f;
+
+/// Status register bit that is set while a program or erase is in progress.
+const STATUS_BUSY: u8 = 0b0000_0001;
+/// Programming can't cross a page boundary.
+const PAGE_SIZE: u32 = 256;
+const SECTOR_SIZE: u32 = 4096;
+/// Status reads before a program or erase is considered stuck. A read takes at least 2 us at
+/// 8 MHz, so this is more than the 400 ms a sector erase of the W25Q series takes at most.
+const BUSY_POLLS: u32 = 250_000;
+
+#[derive(Debug)]
+pub enum SpiFlashError<S, P> {
+    Spi(S),
+    Pin(P),
+    OutOfRange,
+    /// The chip stayed busy for longer than a program or erase takes.
+    Timeout,
+}
+
+pub struct SpiNorFlash<SPI, CS> {
+    spi: SPI,
+    cs: CS,
+    /// Size in bytes, zero until probed.
+    capacity: u32,
+}
+
+impl<SPI: SpiBus, CS: OutputPin> SpiNorFlash<SPI, CS> {
+    pub fn new(spi: SPI, cs: CS) -> Self {
+        Self {
+            spi,
+            cs,
+            capacity: 0,
+        }
+    }
+
+    /// Perform a transaction; write `command`, then `write`, then read into `read`.
+    fn transaction(
+        &mut self,
+        command: &[u8],
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), SpiFlashError<SPI::Error, CS::Error>> {
+        self.cs.set_low().map_err(SpiFlashError::Pin)?;
+        let spi = &mut self.spi;
+        let result = (|| {
+            spi.write(command)?;
+            if !write.is_empty() {
+                spi.write(write)?;
+            }
+            if !read.is_empty() {
+                spi.read(read)?;
+            }
+            spi.flush()
+        })();
+        self.cs.set_high().map_err(SpiFlashError::Pin)?;
+        result.map_err(SpiFlashError::Spi)
+    }
+
+    fn address_command(command: u8, address: u32) -> [u8; 4] {
+        let a = address.to_be_bytes();
+        [command, a[1], a[2], a[3]]
+    }
+
+    /// Read the manufacturer, memory type and capacity bytes.
+    pub fn read_jedec_id(&mut self) -> Result<[u8; 3], SpiFlashError<SPI::Error, CS::Error>> {
+        let mut id = [0u8; 3];
+        self.transaction(&[CMD_JEDEC_ID], &[], &mut id)?;
+        Ok(id)
+    }
+
+    /// Determine whether a chip is present and its capacity, returns false if there is none.
+    pub fn probe(&mut self) -> Result<bool, SpiFlashError<SPI::Error, CS::Error>> {
+        let id = self.read_jedec_id()?;
+        // Capacity is reported as a power of two, only 3 byte addressing is supported.
+        self.capacity = match (id[0], id[2]) {
+            (0x00 | 0xff, _) => 0,
+            (_, bits @ 16..=24) => 1 << bits,
+            _ => 0,
+        };
+        Ok(self.capacity != 0)
+    }
+
+    pub fn read_status(&mut self) -> Result<u8, SpiFlashError<SPI::Error, CS::Error>> {
+        let mut status = [0u8];
+        self.transaction(&[CMD_READ_STATUS], &[], &mut status)?;
+        Ok(status[0])
+    }
+
+    fn wait_idle(&mut self) -> Result<(), SpiFlashError<SPI::Error, CS::Error>> {
+        for _ in 0..BUSY_POLLS {
+            if self.read_status()? & STATUS_BUSY == 0 {
+                return Ok(());
+            }
+        }
+        Err(SpiFlashError::Timeout)
+    }
+
+    fn check_range(
+        &self,
+        address: u32,
+        len: usize,
+    ) -> Result<(), SpiFlashError<SPI::Error, CS::Error>> {
+        match address.checked_add(len as u32) {
+            Some(end) if end <= self.capacity => Ok(()),
+            _ => Err(SpiFlashError::OutOfRange),
+        }
+    }
+}
+
+impl<SPI: SpiBus, CS: OutputPin> Storage for SpiNorFlash<SPI, CS> {
+    type Error = SpiFlashError<SPI::Error, CS::Error>;
+
+    fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    fn sector_size(&self) -> u32 {
+        SECTOR_SIZE
+    }
+
+    fn read(&mut self, address: u32, data: &mut [u8]) -> Result<(), Self::Error> {
+        self.check_range(address, data.len())?;
+        self.transaction(&Self::address_command(CMD_READ, address), &[], data)
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error> {
+        self.check_range(address, data.len())?;
+        let mut address = address;
+        let mut data = data;
+        while !data.is_empty() {
+            let in_page = ((PAGE_SIZE - address % PAGE_SIZE) as usize).min(data.len());
+            let (chunk, remainder) = data.split_at(in_page);
+            self.transaction(&[CMD_WRITE_ENABLE], &[], &mut [])?;
+            self.transaction(
+                &Self::address_command(CMD_PAGE_PROGRAM, address),
+                chunk,
+                &mut [],
+            )?;
+            self.wait_idle()?;
+            address += in_page as u32;
+            data = remainder;
+        }
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, address: u32) -> Result<(), Self::Error> {
+        self.check_range(address, 1)?;
+        self.transaction(&[CMD_WRITE_ENABLE], &[], &mut [])?;
+        self.transaction(
+            &Self::address_command(CMD_SECTOR_ERASE, address),
+            &[],
+            &mut [],
+        )?;
+        self.wait_idle()
+    }
+}
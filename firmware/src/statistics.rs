@@ -0,0 +1,158 @@
+//! Statistics of the temperature since the last reset.
+//!
+//! Tracks the extremes and when they occurred, the mean and how long the temperature spent above a
+//! threshold, such as the liquidus of the solder during reflow.
+use crate::display::Measurement;
+
+/// Default threshold, the liquidus of SAC305 solder.
+pub const DEFAULT_THRESHOLD: f32 = 217.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Statistics {
+    /// Time of the last reset.
+    since: u32,
+    count: u32,
+    min: Measurement,
+    max: Measurement,
+    /// Sum of all temperatures, f32 would lose the resolution of the sensor after a few hours.
+    sum: f64,
+    threshold: f32,
+    /// Time spent above the threshold in milliseconds.
+    above: u32,
+    last: Measurement,
+}
+
+impl Statistics {
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            since: 0,
+            count: 0,
+            min: Default::default(),
+            max: Default::default(),
+            sum: 0.0,
+            threshold,
+            above: 0,
+            last: Default::default(),
+        }
+    }
+
+    /// Forget everything but the threshold, starting over at `time`.
+    pub fn reset(&mut self, time: u32) {
+        *self = Self {
+            since: time,
+            ..Self::new(self.threshold)
+        };
+    }
+
+    pub fn add(&mut self, time: u32, temperature: f32) {
+        let m = Measurement { time, temperature };
+        if self.count == 0 {
+            self.min = m;
+            self.max = m;
+        } else {
+            // The interval counts as above if it started above the threshold.
+            if self.last.temperature > self.threshold {
+                self.above = self.above.wrapping_add(time.wrapping_sub(self.last.time));
+            }
+            if temperature < self.min.temperature {
+                self.min = m;
+            }
+            if temperature > self.max.temperature {
+                self.max = m;
+            }
+        }
+        self.count += 1;
+        self.sum += temperature as f64;
+        self.last = m;
+    }
+
+    /// Time of the last reset.
+    pub fn since(&self) -> u32 {
+        self.since
+    }
+
+    /// Number of samples since the last reset.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// The lowest temperature and when it was first reached.
+    pub fn min(&self) -> Option<Measurement> {
+        (self.count != 0).then_some(self.min)
+    }
+
+    /// The peak temperature and when it was first reached.
+    pub fn max(&self) -> Option<Measurement> {
+        (self.count != 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f32> {
+        (self.count != 0).then(|| (self.sum / self.count as f64) as f32)
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// Change the threshold, the time above it so far is kept.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Time spent above the threshold in milliseconds.
+    pub fn time_above(&self) -> u32 {
+        self.above
+    }
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self::new(DEFAULT_THRESHOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_statistics() {
+        let mut s = Statistics::new(100.0);
+        assert_eq!(s.min(), None);
+        assert_eq!(s.max(), None);
+        assert_eq!(s.mean(), None);
+
+        let profile = [20.0, 90.0, 110.0, 150.0, 150.0, 105.0, 95.0, 30.0];
+        for (i, t) in profile.iter().enumerate() {
+            s.add(1000 + i as u32 * 500, *t);
+        }
+        assert_eq!(s.count(), 8);
+        assert_eq!(
+            s.min(),
+            Some(Measurement {
+                time: 1000,
+                temperature: 20.0
+            })
+        );
+        // The first time the peak was reached.
+        assert_eq!(
+            s.max(),
+            Some(Measurement {
+                time: 2500,
+                temperature: 150.0
+            })
+        );
+        assert_eq!(s.mean(), Some(750.0 / 8.0));
+        // Samples at 110, 150, 150 and 105 each start an interval above the threshold.
+        assert_eq!(s.time_above(), 4 * 500);
+
+        s.reset(5000);
+        assert_eq!(s.since(), 5000);
+        assert_eq!(s.count(), 0);
+        assert_eq!(s.time_above(), 0);
+        assert_eq!(s.threshold(), 100.0);
+        s.add(5000, 200.0);
+        s.add(6000, 200.0);
+        assert_eq!(s.time_above(), 1000);
+        assert_eq!(s.mean(), Some(200.0));
+    }
+}
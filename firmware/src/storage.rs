@@ -0,0 +1,109 @@
+//! Non-volatile storage, used to log runs without a host connected.
+//!
+//! The semantics follow NOR flash; storage is erased in sectors, erased bytes read as `0xff` and
+//! a write can only clear bits. The RAM backed implementation behaves the same way, such that the
+//! logging on top of this can be tested on the host.
+
+pub trait Storage {
+    type Error: core::fmt::Debug;
+
+    /// Total size in bytes.
+    fn capacity(&self) -> u32;
+
+    /// Size of an erase sector in bytes.
+    fn sector_size(&self) -> u32;
+
+    /// Read `data.len()` bytes starting at `address`.
+    fn read(&mut self, address: u32, data: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write bytes starting at `address`, these should have been erased.
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Erase the sector that contains `address`.
+    fn erase_sector(&mut self, address: u32) -> Result<(), Self::Error>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RamStorageError {
+    OutOfRange,
+}
+
+/// Storage backed by a plain array.
+pub struct RamStorage<const N: usize> {
+    data: [u8; N],
+    sector_size: u32,
+}
+
+impl<const N: usize> RamStorage<N> {
+    pub fn new(sector_size: u32) -> Self {
+        Self {
+            data: [0xff; N],
+            sector_size,
+        }
+    }
+
+    fn range(&self, address: u32, len: usize) -> Result<core::ops::Range<usize>, RamStorageError> {
+        let start = address as usize;
+        let end = start.checked_add(len).ok_or(RamStorageError::OutOfRange)?;
+        if end > N {
+            return Err(RamStorageError::OutOfRange);
+        }
+        Ok(start..end)
+    }
+}
+
+impl<const N: usize> Storage for RamStorage<N> {
+    type Error = RamStorageError;
+
+    fn capacity(&self) -> u32 {
+        N as u32
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+
+    fn read(&mut self, address: u32, data: &mut [u8]) -> Result<(), Self::Error> {
+        let range = self.range(address, data.len())?;
+        data.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error> {
+        let range = self.range(address, data.len())?;
+        for (stored, new) in self.data[range].iter_mut().zip(data.iter()) {
+            // Like flash, writing can only clear bits.
+            *stored &= *new;
+        }
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, address: u32) -> Result<(), Self::Error> {
+        let start = address - (address % self.sector_size);
+        let range = self.range(start, self.sector_size as usize)?;
+        self.data[range].fill(0xff);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_ram_storage() {
+        let mut s = RamStorage::<64>::new(16);
+        let mut data = [0u8; 4];
+        s.read(0, &mut data).unwrap();
+        assert_eq!(data, [0xff; 4]);
+        s.write(18, &[1, 2, 3, 4]).unwrap();
+        // Writing can only clear bits.
+        s.write(18, &[0xff, 0x02, 0x01, 0x00]).unwrap();
+        s.read(18, &mut data).unwrap();
+        assert_eq!(data, [1, 2, 1, 0]);
+        s.erase_sector(20).unwrap();
+        s.read(18, &mut data).unwrap();
+        assert_eq!(data, [0xff; 4]);
+        assert_eq!(s.write(62, &[0; 4]), Err(RamStorageError::OutOfRange));
+        assert_eq!(s.read(64, &mut data), Err(RamStorageError::OutOfRange));
+    }
+}
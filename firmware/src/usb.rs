@@ -0,0 +1,153 @@
+#![allow(unsafe_code)]
+//! USB CDC serial port, serviced entirely from the USB interrupt.
+//!
+//! The main loop only pushes into the transmit ring buffer and pends the interrupt, the interrupt
+//! polls the device and moves data from the ring into the serial port whenever the host has room
+//! for it. This keeps enumeration and host reads independent of what the main loop is doing.
+//! Received data goes the other way, through the receive ring buffer.
+use core::cell::RefCell;
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use cortex_m::interrupt::Mutex;
+use cortex_m::peripheral::NVIC;
+use stm32f1xx_hal::pac::Interrupt;
+use stm32f1xx_hal::pac::interrupt;
+use stm32f1xx_hal::usb::{Peripheral, UsbBus};
+
+use usb_device::bus::UsbBusAllocator;
+use usb_device::prelude::*;
+use usbd_serial::{SerialPort, USB_CLASS_CDC};
+
+use crate::ring::RingBuffer;
+
+type Bus = UsbBus<Peripheral>;
+
+struct UsbState {
+    device: UsbDevice<'static, Bus>,
+    serial: SerialPort<'static, Bus>,
+}
+
+static GLOBAL_USB: Mutex<RefCell<Option<UsbState>>> = Mutex::new(RefCell::new(None));
+/// Transmit buffer, large enough to ride out the host not reading for a few seconds.
+static TX: RingBuffer<4096> = RingBuffer::new();
+/// Number of lines dropped because they didn't fit.
+static TX_DROPPED: AtomicU32 = AtomicU32::new(0);
+/// Whether a host has the port open, from the DTR signal.
+static CONNECTED: AtomicBool = AtomicBool::new(false);
+/// Receive buffer, bytes that don't fit are dropped.
+static RX: RingBuffer<128> = RingBuffer::new();
+
+impl UsbState {
+    fn service(&mut self) {
+        self.device.poll(&mut [&mut self.serial]);
+        CONNECTED.store(
+            self.device.state() == UsbDeviceState::Configured && self.serial.dtr(),
+            Ordering::Relaxed,
+        );
+
+        // Always read the input, such that the host doesn't stall if the main loop falls behind.
+        let mut received = [0u8; 64];
+        if let Ok(count) = self.serial.read(&mut received) {
+            RX.push(&received[0..count]);
+        }
+
+        // Move pending output into the serial port, until it doesn't accept any more.
+        loop {
+            let pending = TX.peek();
+            if pending.is_empty() {
+                break;
+            }
+            match self.serial.write(pending) {
+                Ok(count) if count > 0 => TX.consume(count),
+                _ => break,
+            }
+        }
+    }
+}
+
+#[interrupt]
+fn USB_LP_CAN_RX0() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(usb) = GLOBAL_USB.borrow(cs).borrow_mut().deref_mut() {
+            usb.service();
+        }
+    });
+}
+
+/// Handle to the serial port, data is queued here and transferred from the interrupt.
+pub struct Serial {
+    _private: (),
+}
+
+impl Serial {
+    /// Queue `data` as a whole, returns `WouldBlock` and counts a dropped line if it doesn't fit.
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
+        let queued = TX.push_all(data);
+        // Kick the interrupt such that it moves the data into the serial port.
+        NVIC::pend(Interrupt::USB_LP_CAN_RX0);
+        if queued {
+            Ok(data.len())
+        } else {
+            self.count_dropped();
+            Err(UsbError::WouldBlock)
+        }
+    }
+
+    /// Count a line that was dropped before it reached the transmit buffer.
+    pub fn count_dropped(&mut self) {
+        TX_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of lines dropped since boot.
+    pub fn dropped(&self) -> u32 {
+        TX_DROPPED.load(Ordering::Relaxed)
+    }
+
+    /// Whether a host has the port open.
+    pub fn connected(&self) -> bool {
+        CONNECTED.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes that can still be queued.
+    pub fn free(&self) -> usize {
+        TX.free()
+    }
+
+    /// Read a received byte.
+    pub fn read_byte(&mut self) -> Option<u8> {
+        let byte = RX.peek().first().copied()?;
+        RX.consume(1);
+        Some(byte)
+    }
+}
+
+/// Setup the USB device and CDC serial port, and start servicing them from the interrupt.
+pub fn setup(usb: Peripheral) -> Serial {
+    let usb_bus: &'static UsbBusAllocator<Bus> =
+        cortex_m::singleton!(: UsbBusAllocator<Bus> = UsbBus::new(usb)).unwrap();
+
+    let serial = SerialPort::new(usb_bus);
+
+    let device = UsbDeviceBuilder::new(usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .device_class(USB_CLASS_CDC)
+        .strings(&[StringDescriptors::default()
+            .manufacturer("Fake Company")
+            .product("Serial port")
+            .serial_number("TEST")])
+        .unwrap()
+        .build();
+
+    cortex_m::interrupt::free(|cs| {
+        GLOBAL_USB
+            .borrow(cs)
+            .borrow_mut()
+            .replace(UsbState { device, serial });
+    });
+
+    unsafe {
+        NVIC::unmask(Interrupt::USB_LP_CAN_RX0);
+    }
+
+    Serial { _private: () }
+}
@@ -110,15 +110,20 @@ macro_rules! sprintln {
   ($serial:expr, $($arg:tt)*) => ({
     pub use core::fmt::Write;
       let mut v: crate::util::StackString = Default::default();
-      core::fmt::write(&mut v, format_args!($($arg)*)).expect("Error occurred while trying to write in String");
-      v.write_str("\n").expect("Shouldn't fail");
-      // Write, and then drop the result, such that we don't panic if nothing is consuming data from the port.
-      match $serial.write(v.as_slice()){
-        Ok(_count) => {
-            // count bytes were written
-        },
-        Err(UsbError::WouldBlock) => {},// No data could be written (buffers full)
-        Err(_err) => {},// An error occurred
+      // Lines that don't fit the stack string are dropped as a whole, never sent truncated.
+      let formatted = core::fmt::write(&mut v, format_args!($($arg)*)).and_then(|_| v.write_str("\n"));
+      if formatted.is_err() {
+        $serial.count_dropped();
+      } else {
+        // Write, and then drop the result, such that we don't panic if nothing is consuming data from the port.
+        // Lines that don't fit in the transmit buffer are counted by the writer.
+        match $serial.write(v.as_slice()){
+          Ok(_count) => {
+              // count bytes were written
+          },
+          Err(UsbError::WouldBlock) => {},// No data could be written (buffers full)
+          Err(_err) => {},// An error occurred
+        }
       }
   })
 
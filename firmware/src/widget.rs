@@ -0,0 +1,611 @@
+//! Widgets to compose the display pages from, without working out pixel positions by hand.
+//!
+//! A page is a tree of rows and columns with the widgets as leaves. Along a row or column each
+//! widget takes its own size, and the widgets without one share the space that is left. Across a
+//! row or column a widget takes its own size, or the full size if it has none.
+//!
+//! Rendering is incremental: a widget is only drawn if what it shows differs between the old and
+//! the new contents, it then clears its area and never draws outside of it.
+use core::fmt;
+
+use embedded_graphics::draw_target::Clipped;
+use embedded_graphics::mono_font::{MonoFont, MonoTextStyle};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Baseline, Text};
+
+use crate::display::Contents;
+use crate::util::StackString;
+
+pub type ContentFn = fn(&Contents) -> Result<StackString, fmt::Error>;
+
+/// A number with the decimals it is shown with and its unit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quantity {
+    pub value: f32,
+    pub decimals: usize,
+    /// Shown after the number, may be empty.
+    pub unit: &'static str,
+}
+
+pub type QuantityFn = fn(&Contents) -> Quantity;
+
+/// An 8x8 bitmap, a byte per row from the top with the most significant bit on the left.
+pub type Icon = [u8; 8];
+
+const ICON_SIZE: u32 = 8;
+
+/// The icon to show, if any.
+pub type IconFn = fn(&Contents) -> Option<Icon>;
+
+/// The filled fraction of a bar, from 0 to 1.
+pub type FractionFn = fn(&Contents) -> f32;
+
+#[derive(Copy, Clone)]
+enum Kind<'a> {
+    Text {
+        font: &'a MonoFont<'a>,
+        content: ContentFn,
+    },
+    Label {
+        font: &'a MonoFont<'a>,
+        text: &'static str,
+    },
+    Number {
+        font: &'a MonoFont<'a>,
+        chars: usize,
+        spaced: bool,
+        quantity: QuantityFn,
+    },
+    Icon(IconFn),
+    Bar(FractionFn),
+    Sparkline,
+    Space(u32),
+    Fill,
+    Row(&'a [Widget<'a>]),
+    Column(&'a [Widget<'a>]),
+}
+
+/// An element of a page, see the module documentation.
+#[derive(Copy, Clone)]
+pub struct Widget<'a> {
+    kind: Kind<'a>,
+    /// Sizes set with `width` and `height`, these take precedence over the natural size.
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+impl<'a> Widget<'a> {
+    const fn new(kind: Kind<'a>) -> Self {
+        Self {
+            kind,
+            width: None,
+            height: None,
+        }
+    }
+
+    /// A line of text from the contents, a line of the font high.
+    pub const fn text(font: &'a MonoFont<'a>, content: ContentFn) -> Self {
+        Self::new(Kind::Text { font, content })
+    }
+
+    /// Fixed text, exactly as wide as the text.
+    pub const fn label(font: &'a MonoFont<'a>, text: &'static str) -> Self {
+        Self::new(Kind::Label { font, text })
+    }
+
+    /// A number right aligned in `chars` characters followed by its unit, see `fitted`.
+    pub const fn number(font: &'a MonoFont<'a>, chars: usize, quantity: QuantityFn) -> Self {
+        Self::new(Kind::Number {
+            font,
+            chars,
+            spaced: false,
+            quantity,
+        })
+    }
+
+    /// An 8x8 icon, blank if there is none to show.
+    pub const fn icon(icon: IconFn) -> Self {
+        Self::new(Kind::Icon(icon))
+    }
+
+    /// A horizontal bar graph in a frame, filled from the left.
+    pub const fn bar(fraction: FractionFn) -> Self {
+        Self::new(Kind::Bar(fraction))
+    }
+
+    /// The graph of recent temperatures.
+    pub const fn sparkline() -> Self {
+        Self::new(Kind::Sparkline)
+    }
+
+    /// Empty space of `size` pixels in both directions.
+    pub const fn space(size: u32) -> Self {
+        Self::new(Kind::Space(size))
+    }
+
+    /// Empty space that takes what is left.
+    pub const fn fill() -> Self {
+        Self::new(Kind::Fill)
+    }
+
+    /// Widgets side by side, from the left.
+    pub const fn row(children: &'a [Widget<'a>]) -> Self {
+        Self::new(Kind::Row(children))
+    }
+
+    /// Widgets below each other, from the top.
+    pub const fn column(children: &'a [Widget<'a>]) -> Self {
+        Self::new(Kind::Column(children))
+    }
+
+    pub const fn width(mut self, width: u32) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    pub const fn height(mut self, height: u32) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// Separate the unit of a number with a space.
+    pub const fn spaced(mut self) -> Self {
+        if let Kind::Number { spaced, .. } = &mut self.kind {
+            *spaced = true;
+        }
+        self
+    }
+
+    /// The width if `horizontal` or else the height, `None` for a widget that takes what it gets.
+    ///
+    /// A row or column is as long as its children together and as thick as the thickest, unless
+    /// a child takes what it gets.
+    fn extent(&self, horizontal: bool) -> Option<u32> {
+        let set = if horizontal { self.width } else { self.height };
+        if set.is_some() {
+            return set;
+        }
+        match self.kind {
+            Kind::Text { font, .. } | Kind::Number { font, .. } => {
+                (!horizontal).then_some(font.character_size.height)
+            }
+            Kind::Label { font, text } => Some(if horizontal {
+                text.len() as u32 * (font.character_size.width + font.character_spacing)
+            } else {
+                font.character_size.height
+            }),
+            Kind::Icon(_) => Some(ICON_SIZE),
+            Kind::Space(size) => Some(size),
+            Kind::Bar(_) | Kind::Sparkline | Kind::Fill => None,
+            Kind::Row(children) | Kind::Column(children) => {
+                let along = matches!(self.kind, Kind::Row(_)) == horizontal;
+                let mut extents = children.iter().map(|w| w.extent(horizontal));
+                if along {
+                    extents.sum()
+                } else {
+                    extents.try_fold(0, |max, e| Some(e?.max(max)))
+                }
+            }
+        }
+    }
+
+    /// Draw the widget in `area` where the new contents differ from the old, everything if there
+    /// are no old contents.
+    pub fn render<DT: DrawTarget<Color = BinaryColor>>(
+        &self,
+        area: Rectangle,
+        old_contents: Option<&Contents>,
+        new_contents: &Contents,
+        target: &mut DT,
+    ) -> Result<(), DT::Error> {
+        match self.kind {
+            Kind::Text { font, content } => render_text(
+                font,
+                &area,
+                old_contents.map(content),
+                content(new_contents),
+                target,
+            ),
+            Kind::Label { font, text } => {
+                let text = || Ok(StackString::from_str(text));
+                render_text(font, &area, old_contents.map(|_| text()), text(), target)
+            }
+            Kind::Number {
+                font,
+                chars,
+                spaced,
+                quantity,
+            } => {
+                let text = |c: &Contents| {
+                    let q = quantity(c);
+                    StackString::from_format(format_args!(
+                        "{}{}{}",
+                        fitted(q.value, q.decimals, chars),
+                        if spaced && !q.unit.is_empty() {
+                            " "
+                        } else {
+                            ""
+                        },
+                        q.unit
+                    ))
+                };
+                render_text(
+                    font,
+                    &area,
+                    old_contents.map(text),
+                    text(new_contents),
+                    target,
+                )
+            }
+            Kind::Icon(icon) => redraw(
+                &area,
+                old_contents.map(icon),
+                icon(new_contents),
+                target,
+                |icon, target| {
+                    let Some(icon) = icon else {
+                        return Ok(());
+                    };
+                    let origin = area.top_left;
+                    target.draw_iter((0..ICON_SIZE as i32).flat_map(move |y| {
+                        (0..ICON_SIZE as i32)
+                            .filter(move |x| icon[y as usize] & (0x80 >> x) != 0)
+                            .map(move |x| Pixel(origin + Point::new(x, y), BinaryColor::On))
+                    }))
+                },
+            ),
+            Kind::Bar(fraction) => {
+                // Compared by the filled width, changes that don't show aren't drawn.
+                let inner = area.size.saturating_sub(Size::new(2, 2));
+                let filled =
+                    |c: &Contents| (fraction(c).clamp(0.0, 1.0) * inner.width as f32 + 0.5) as u32;
+                redraw(
+                    &area,
+                    old_contents.map(filled),
+                    filled(new_contents),
+                    target,
+                    |filled, target| {
+                        area.into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+                            .draw(target)?;
+                        let bar = Rectangle::new(
+                            area.top_left + Point::new(1, 1),
+                            Size::new(filled, inner.height),
+                        );
+                        target.fill_solid(&bar, BinaryColor::On)
+                    },
+                )
+            }
+            Kind::Sparkline => {
+                // The graph draws all its pixels, only the changed columns end up being flushed.
+                if old_contents.map(|c| c.graph) != Some(new_contents.graph) {
+                    new_contents.graph.draw(&area, target)?;
+                }
+                Ok(())
+            }
+            Kind::Space(_) | Kind::Fill => Ok(()),
+            Kind::Row(children) | Kind::Column(children) => {
+                let horizontal = matches!(self.kind, Kind::Row(_));
+                for (child, child_area) in split(children, area, horizontal) {
+                    child.render(child_area, old_contents, new_contents, target)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The areas of `children` in `area`, side by side if `horizontal` or else below each other.
+///
+/// The space left by the children with a size is shared evenly by those without. Children that
+/// don't fit are cut off at the end of the area.
+fn split<'b>(
+    children: &'b [Widget<'b>],
+    area: Rectangle,
+    horizontal: bool,
+) -> impl Iterator<Item = (&'b Widget<'b>, Rectangle)> {
+    let (length, across) = if horizontal {
+        (area.size.width, area.size.height)
+    } else {
+        (area.size.height, area.size.width)
+    };
+    let fixed: u32 = children.iter().filter_map(|w| w.extent(horizontal)).sum();
+    let fills = children
+        .iter()
+        .filter(|w| w.extent(horizontal).is_none())
+        .count() as u32;
+    let left = length.saturating_sub(fixed);
+    let mut offset = 0;
+    let mut filled = 0;
+    children.iter().map(move |w| {
+        let size = match w.extent(horizontal) {
+            Some(size) => size,
+            None => {
+                filled += 1;
+                left * filled / fills - left * (filled - 1) / fills
+            }
+        }
+        .min(length - offset);
+        let thickness = w.extent(!horizontal).map_or(across, |t| t.min(across));
+        let (position, size2) = if horizontal {
+            (Point::new(offset as i32, 0), Size::new(size, thickness))
+        } else {
+            (Point::new(0, offset as i32), Size::new(thickness, size))
+        };
+        offset += size;
+        (w, Rectangle::new(area.top_left + position, size2))
+    })
+}
+
+/// Draw `new` if it differs from `old`, after clearing `area` and clipped to it.
+fn redraw<DT, T, F>(
+    area: &Rectangle,
+    old: Option<T>,
+    new: T,
+    target: &mut DT,
+    draw: F,
+) -> Result<(), DT::Error>
+where
+    DT: DrawTarget<Color = BinaryColor>,
+    T: PartialEq,
+    F: FnOnce(T, &mut Clipped<'_, DT>) -> Result<(), DT::Error>,
+{
+    if old.as_ref() == Some(&new) {
+        return Ok(());
+    }
+    target.fill_solid(area, BinaryColor::Off)?;
+    draw(new, &mut target.clipped(area))
+}
+
+/// Text that doesn't fit is cut off at the edge of the area, text that failed to format leaves
+/// it blank.
+fn render_text<DT: DrawTarget<Color = BinaryColor>>(
+    font: &MonoFont,
+    area: &Rectangle,
+    old: Option<Result<StackString, fmt::Error>>,
+    new: Result<StackString, fmt::Error>,
+    target: &mut DT,
+) -> Result<(), DT::Error> {
+    redraw(area, old, new, target, |new, target| {
+        if let Ok(v) = new
+            && let Ok(s) = v.as_str()
+        {
+            let style = MonoTextStyle::new(font, BinaryColor::On);
+            Text::with_baseline(s, area.top_left, style, Baseline::Top).draw(target)?;
+        }
+        Ok(())
+    })
+}
+
+/// The number of decimals, at most `decimals`, with which `value` fits in `width` characters.
+fn fitting_decimals(value: f32, mut decimals: usize, width: usize) -> usize {
+    while decimals > 0 {
+        match StackString::from_format(format_args!("{:.*}", decimals, value)) {
+            Ok(s) if s.len() > width => decimals -= 1,
+            _ => break,
+        }
+    }
+    decimals
+}
+
+/// A number right aligned in a field of a fixed number of characters, see `fitted`.
+pub struct Fitted {
+    value: f32,
+    decimals: usize,
+    width: usize,
+}
+
+/// Show `value` right aligned in `width` characters, with at most `decimals` decimals and fewer if
+/// it wouldn't fit otherwise. Like on a multimeter NaN is shown as `Err`, and values that don't fit
+/// at all, including the infinities, as `OL` or `-OL`.
+pub fn fitted(value: f32, decimals: usize, width: usize) -> Fitted {
+    Fitted {
+        value,
+        decimals,
+        width,
+    }
+}
+
+impl fmt::Display for Fitted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = if self.value.is_nan() {
+            StackString::from_str("Err")
+        } else {
+            let decimals = fitting_decimals(self.value, self.decimals, self.width);
+            match StackString::from_format(format_args!("{:.*}", decimals, self.value)) {
+                Ok(s) if self.value.is_finite() && s.len() <= self.width => s,
+                _ if self.value > 0.0 => StackString::from_str("OL"),
+                _ => StackString::from_str("-OL"),
+            }
+        };
+        let text = text.as_str().map_err(|_| fmt::Error)?;
+        write!(f, "{: >1$}", text, self.width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::mono_font::ascii::FONT_6X10;
+    use embedded_graphics::mono_font::iso_8859_9::FONT_5X7;
+
+    fn lit_in(display: &MockDisplay<BinaryColor>, area: Rectangle) -> usize {
+        area.points()
+            .filter(|p| display.get_pixel(*p) == Some(BinaryColor::On))
+            .count()
+    }
+
+    fn time(c: &Contents) -> Result<StackString, fmt::Error> {
+        StackString::from_format(format_args!("{}", c.time))
+    }
+
+    #[test]
+    fn test_fitting_decimals() {
+        assert_eq!(fitting_decimals(24.6875, 4, 7), 4);
+        assert_eq!(fitting_decimals(24.6875, 2, 7), 2);
+        assert_eq!(fitting_decimals(-1337.0, 4, 7), 1);
+        assert_eq!(fitting_decimals(24.6875, 4, 3), 0);
+        // Integers that don't fit are still shown whole.
+        assert_eq!(fitting_decimals(1337.0, 2, 3), 0);
+    }
+
+    #[test]
+    fn test_fitted() {
+        let f = |v, decimals, width| format!("{}", fitted(v, decimals, width));
+        assert_eq!(f(24.6875, 2, 7), "  24.69");
+        assert_eq!(f(-1337.0, 4, 7), "-1337.0");
+        assert_eq!(f(1e6, 2, 5), "   OL");
+        assert_eq!(f(-1e6, 2, 5), "  -OL");
+        assert_eq!(f(f32::INFINITY, 2, 7), "     OL");
+        assert_eq!(f(f32::NEG_INFINITY, 2, 7), "    -OL");
+        assert_eq!(f(f32::NAN, 2, 7), "    Err");
+        // Wider than the stack string the number is formatted into.
+        assert_eq!(f(f32::MAX, 4, 11), "         OL");
+    }
+
+    #[test]
+    fn test_text() {
+        let field = Widget::text(&FONT_5X7, time);
+        let area = Rectangle::new(Point::zero(), Size::new(12, 7));
+        let right = Rectangle::new(Point::new(12, 0), Size::new(52, 7));
+        let mut display = MockDisplay::new();
+        display.set_allow_overdraw(true);
+
+        // Text that doesn't fit is cut off at the edge of the field.
+        let wide = Contents {
+            time: 88888,
+            ..Default::default()
+        };
+        field.render(area, None, &wide, &mut display).unwrap();
+        assert!(lit_in(&display, area) > 0);
+        assert_eq!(lit_in(&display, right), 0);
+
+        // A shorter value clears the rest of the field.
+        let short = Contents {
+            time: 1,
+            ..Default::default()
+        };
+        field
+            .render(area, Some(&wide), &short, &mut display)
+            .unwrap();
+        assert!(lit_in(&display, area) > 0);
+        let after = Rectangle::new(Point::new(5, 0), Size::new(7, 7));
+        assert_eq!(lit_in(&display, after), 0);
+
+        // Unchanged content isn't drawn at all.
+        let mut untouched = MockDisplay::new();
+        field
+            .render(area, Some(&short), &short, &mut untouched)
+            .unwrap();
+        assert_eq!(untouched, MockDisplay::new());
+    }
+
+    #[test]
+    fn test_layout() {
+        let column = [
+            Widget::text(&FONT_5X7, time),
+            Widget::fill(),
+            Widget::text(&FONT_6X10, time).width(20),
+            Widget::space(2),
+            Widget::fill(),
+        ];
+        let row = [
+            Widget::label(&FONT_5X7, "ab "),
+            Widget::column(&column),
+            Widget::icon(|_| None),
+            Widget::sparkline(),
+        ];
+        let area = Rectangle::new(Point::new(10, 20), Size::new(64, 32));
+        let areas: Vec<_> = split(&row, area, true).map(|(_, a)| a).collect();
+        let rect = |x, y, w, h| Rectangle::new(Point::new(x, y), Size::new(w, h));
+        // The column and the sparkline share what the label and the icon leave.
+        assert_eq!(
+            areas,
+            [
+                rect(10, 20, 15, 7),
+                rect(25, 20, 20, 32),
+                rect(45, 20, 8, 8),
+                rect(53, 20, 21, 32),
+            ]
+        );
+        assert_eq!(Widget::column(&column).extent(false), None);
+        assert_eq!(Widget::row(&row[..1]).extent(true), Some(15));
+
+        // Spaces that fill share what is left as evenly as they can.
+        let areas: Vec<_> = split(&column, areas[1], false).map(|(_, a)| a).collect();
+        assert_eq!(
+            areas,
+            [
+                rect(25, 20, 20, 7),
+                rect(25, 27, 20, 6),
+                rect(25, 33, 20, 10),
+                rect(25, 43, 2, 2),
+                rect(25, 45, 20, 7),
+            ]
+        );
+
+        // What doesn't fit is cut off.
+        let tall = [Widget::space(20), Widget::text(&FONT_6X10, time)];
+        let areas: Vec<_> = split(&tall, rect(0, 0, 8, 24), false)
+            .map(|(_, a)| a)
+            .collect();
+        assert_eq!(areas, [rect(0, 0, 8, 20), rect(0, 20, 8, 4)]);
+    }
+
+    #[test]
+    fn test_widgets() {
+        let contents = Contents {
+            temperature: 24.6875,
+            cold_junction: 0.25,
+            alerts: [true, false, false, false],
+            ..Default::default()
+        };
+        let widgets = [
+            Widget::number(&FONT_5X7, 5, |c| Quantity {
+                value: c.temperature,
+                decimals: 1,
+                unit: "C",
+            })
+            .spaced()
+            .width(35),
+            Widget::icon(|c| c.alerts[0].then_some([0xff; 8])),
+            Widget::bar(|c| c.cold_junction).height(6),
+        ];
+        let page = Widget::row(&widgets);
+        let area = Rectangle::new(Point::zero(), Size::new(64, 8));
+        let number = Rectangle::new(Point::zero(), Size::new(35, 7));
+        let icon = Rectangle::new(Point::new(35, 0), Size::new(8, 8));
+        let bar = Rectangle::new(Point::new(43, 0), Size::new(21, 6));
+
+        let mut display = MockDisplay::new();
+        display.set_allow_overdraw(true);
+        page.render(area, None, &contents, &mut display).unwrap();
+        assert!(lit_in(&display, number) > 0);
+        assert_eq!(lit_in(&display, icon), 64);
+        // A frame around a quarter of the 19 pixels inside.
+        assert_eq!(lit_in(&display, bar), 2 * 21 + 2 * 4 + 5 * 4);
+
+        // Only what changed is drawn, the icon is cleared.
+        let next = Contents {
+            alerts: [false; 4],
+            cold_junction: 0.26,
+            ..contents
+        };
+        let mut changes = MockDisplay::new();
+        page.render(area, Some(&contents), &next, &mut changes)
+            .unwrap();
+        assert_eq!(changes.affected_area(), icon);
+
+        // A bar doesn't overflow its frame.
+        let over = Contents {
+            cold_junction: 2.0,
+            ..contents
+        };
+        let mut display = MockDisplay::new();
+        display.set_allow_overdraw(true);
+        page.render(area, None, &over, &mut display).unwrap();
+        assert_eq!(lit_in(&display, bar), 21 * 6);
+    }
+}